@@ -0,0 +1,171 @@
+use crate::config::{self, get_config_path, Config};
+use crate::database::Database;
+use crate::profile;
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// バックアップはこの接頭辞に作成日時を付けたディレクトリに保存する
+const BACKUP_PREFIX: &str = "backup-";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S%.3f";
+pub const DATABASE_FILE: &str = "chat_history.db";
+const CONFIG_FILE: &str = "config.json";
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BackupInfo {
+    pub name: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    // データベースファイルのサイズ（バイト）
+    pub size: u64,
+    pub encrypted: bool,
+    #[serde(rename = "hasConfig")]
+    pub has_config: bool,
+}
+
+// バックアップの保存先（未設定の場合はプロファイルのデータディレクトリのbackups）
+pub fn backup_dir(app_handle: &tauri::AppHandle, config: &Config) -> Result<PathBuf, String> {
+    let dir = match &config.backup_dir {
+        Some(dir) if !dir.trim().is_empty() => PathBuf::from(dir),
+        _ => profile::data_dir_of(app_handle, &profile::active(app_handle)?)?.join("backups"),
+    };
+
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    Ok(dir)
+}
+
+// 名前からバックアップのディレクトリを求める（保存先の外を指す名前は受け付けない）
+pub fn backup_path(app_handle: &tauri::AppHandle, config: &Config, name: &str) -> Result<PathBuf, String> {
+    if !name.starts_with(BACKUP_PREFIX) || name.contains(['/', '\\']) || name.contains("..") {
+        return Err(format!("Invalid backup name: {}", name));
+    }
+
+    let path = backup_dir(app_handle, config)?.join(name);
+    if !path.join(DATABASE_FILE).exists() {
+        return Err(format!("Backup not found: {}", name));
+    }
+
+    Ok(path)
+}
+
+fn parse_created_at(name: &str) -> Option<NaiveDateTime> {
+    let timestamp = name.strip_prefix(BACKUP_PREFIX)?;
+    NaiveDateTime::parse_from_str(timestamp, BACKUP_TIME_FORMAT).ok()
+}
+
+fn read_info(path: &Path) -> Option<BackupInfo> {
+    let name = path.file_name()?.to_str()?.to_string();
+    let created_at = parse_created_at(&name)?.and_utc().to_rfc3339();
+    let db_file = path.join(DATABASE_FILE);
+    let size = fs::metadata(&db_file).ok()?.len();
+
+    Some(BackupInfo {
+        name,
+        created_at,
+        size,
+        encrypted: Database::is_encrypted_path(&db_file).unwrap_or(false),
+        has_config: path.join(CONFIG_FILE).exists(),
+    })
+}
+
+// バックアップの一覧（新しい順）
+pub fn list_backups(app_handle: &tauri::AppHandle, config: &Config) -> Result<Vec<BackupInfo>, String> {
+    let dir = backup_dir(app_handle, config)?;
+
+    let mut backups: Vec<BackupInfo> = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read backup directory: {}", e))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| read_info(&entry.path()))
+        .collect();
+
+    // 名前は作成日時順に並ぶ
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+
+    Ok(backups)
+}
+
+// データベースと設定ファイルのバックアップを作成する
+// rotateを指定した場合は、保持数を超えた古いバックアップを削除する
+// （復元前のバックアップでは、復元しようとしているバックアップを消さないように削除しない）
+pub fn create_backup(app_handle: &tauri::AppHandle, database: &Database, config: &Config, rotate: bool) -> Result<BackupInfo, String> {
+    let name = format!("{}{}", BACKUP_PREFIX, Utc::now().format(BACKUP_TIME_FORMAT));
+    let path = backup_dir(app_handle, config)?.join(&name);
+    fs::create_dir_all(&path)
+        .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+
+    let result = (|| {
+        let db_file = path.join(DATABASE_FILE);
+        database.backup_to(&db_file)
+            .map_err(|e| format!("Failed to back up database: {}", e))?;
+        Database::check_integrity(&db_file, database.passphrase())?;
+
+        let config_path = get_config_path(app_handle)?;
+        if config_path.exists() {
+            fs::copy(&config_path, path.join(CONFIG_FILE))
+                .map_err(|e| format!("Failed to back up config: {}", e))?;
+        }
+
+        Ok(())
+    })();
+
+    // 失敗した場合は不完全なバックアップを残さない
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&path);
+        return Err(e);
+    }
+
+    if rotate {
+        rotate_backups(app_handle, config)?;
+    }
+
+    read_info(&path).ok_or_else(|| "Failed to read backup".to_string())
+}
+
+// 保持数を超えた古いバックアップを削除する（0の場合は削除しない）
+fn rotate_backups(app_handle: &tauri::AppHandle, config: &Config) -> Result<(), String> {
+    if config.backup_keep == 0 {
+        return Ok(());
+    }
+
+    let dir = backup_dir(app_handle, config)?;
+    for backup in list_backups(app_handle, config)?.into_iter().skip(config.backup_keep) {
+        fs::remove_dir_all(dir.join(&backup.name))
+            .map_err(|e| format!("Failed to remove old backup: {}", e))?;
+    }
+
+    Ok(())
+}
+
+// 前回のバックアップから設定した間隔が過ぎているか
+pub fn is_backup_due(app_handle: &tauri::AppHandle, config: &Config) -> Result<bool, String> {
+    if config.backup_interval_hours == 0 {
+        return Ok(false);
+    }
+
+    let latest = list_backups(app_handle, config)?
+        .first()
+        .and_then(|backup| parse_created_at(&backup.name));
+
+    Ok(match latest {
+        Some(latest) => Utc::now().naive_utc() - latest >= chrono::Duration::hours(config.backup_interval_hours as i64),
+        None => true,
+    })
+}
+
+// バックアップに含まれる設定を読み込む
+pub fn read_config(path: &Path) -> Result<Option<Config>, String> {
+    let config_file = path.join(CONFIG_FILE);
+    if !config_file.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(&config_file)
+        .map_err(|e| format!("Failed to read config backup: {}", e))?;
+    // 古いバージョンで作成したバックアップも現在の形式に変換して読み込む
+    config::parse_config(&content)
+        .map(|(config, _)| Some(config))
+        .map_err(|e| format!("Failed to parse config backup: {}", e))
+}
@@ -1,62 +1,352 @@
+use crate::profile;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
-use tauri::Manager;
+use std::path::{Path, PathBuf};
 
+// 設定ファイルの形式のバージョン（versionが無いファイルはバージョン1）
+pub const CONFIG_VERSION: u32 = 4;
+
+// バージョンごとの変換（MIGRATIONS[i]はバージョンi+1のファイルをi+2の形式にする）
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[
+    // 1 → 2: キーを画面の型と同じcamelCaseにする
+    migrate_keys_to_camel_case,
+    // 2 → 3: 履歴の最大保存数を無制限にする（以前は設定されていても適用されていなかった）
+    reset_max_history,
+    // 3 → 4: ゴミ箱の自動削除を無効にする（以前はデフォルトで30日後に削除していた）
+    reset_trash_retention_days,
+];
+
+// 項目が足りない場合はデフォルト値を使い、知らない項目は無視する
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default, rename_all = "camelCase")]
 pub struct Config {
+    pub version: u32,
     pub api_key: String,
     pub model: String,
     pub theme: String,
+    // 保存するセッションの上限。超えた古いセッションをゴミ箱に移動する（0の場合は無制限）
     pub max_history: usize,
     pub auto_create_chat: bool,
+    // この日数より長く更新されていないセッションをゴミ箱に移動する（Noneの場合は無効）
+    pub retention_max_age_days: Option<u32>,
+    // ゴミ箱に移動してからこの日数が過ぎた項目を完全に削除する（0の場合は自動削除しない）
+    // 保持ポリシーでゴミ箱に移動したセッションは自動削除しない
+    pub trash_retention_days: u32,
+    // 最初のやり取りの後にモデルでセッションのタイトルを自動生成する
+    pub auto_title: bool,
+    // 暗号化されたデータベースを、操作がないままこの分数が過ぎたらロックする（Noneの場合は無効）
+    pub auto_lock_minutes: Option<u32>,
+    // バックアップの保存先（Noneの場合はアプリのデータディレクトリのbackups）
+    pub backup_dir: Option<String>,
+    // 自動バックアップの間隔（時間、0の場合は自動バックアップしない）
+    pub backup_interval_hours: u32,
+    // 残しておくバックアップの数（0の場合は削除しない）
+    pub backup_keep: usize,
+    // メッセージの埋め込みを計算し、意味の近いメッセージを検索できるようにする
+    pub semantic_search: bool,
+    // OpenAI互換の埋め込みAPIのURL（ローカルのサーバーも指定できる）
+    pub embedding_base_url: String,
+    pub embedding_api_key: Option<String>,
+    pub embedding_model: String,
+}
+
+fn default_auto_title() -> bool {
+    true
+}
+
+fn default_backup_interval_hours() -> u32 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_embedding_base_url() -> String {
+    "https://api.openai.com".to_string()
+}
+
+fn default_embedding_model() -> String {
+    "text-embedding-3-small".to_string()
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             api_key: String::new(),
             model: "claude-3-opus-20240229".to_string(),
             theme: "light".to_string(),
-            max_history: 100,
+            max_history: 0,
             auto_create_chat: true, // デフォルトでは自動作成を有効にする
+            retention_max_age_days: None,
+            trash_retention_days: 0,
+            auto_title: default_auto_title(),
+            auto_lock_minutes: None,
+            backup_dir: None,
+            backup_interval_hours: default_backup_interval_hours(),
+            backup_keep: default_backup_keep(),
+            semantic_search: false,
+            embedding_base_url: default_embedding_base_url(),
+            embedding_api_key: None,
+            embedding_model: default_embedding_model(),
+        }
+    }
+}
+
+// 設定ファイルを読み書きできない場合のエラー
+#[derive(Debug)]
+pub enum ConfigError {
+    // 設定ディレクトリの場所を特定できない
+    Location(String),
+    // ディレクトリやファイルの読み書きに失敗した
+    Io { path: PathBuf, source: std::io::Error },
+    Serialize(serde_json::Error),
+    // 設定ファイルの内容を読み込めなかった（ファイルはpreserved_pathに移し、デフォルト設定を保存し直した）
+    Parse { message: String, preserved_path: PathBuf },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Location(e) => write!(f, "設定ディレクトリを特定できません: {}", e),
+            ConfigError::Io { path, source } => write!(f, "{}を読み書きできません: {}", path.display(), source),
+            ConfigError::Serialize(e) => write!(f, "Failed to serialize config: {}", e),
+            ConfigError::Parse { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for String {
+    fn from(e: ConfigError) -> Self {
+        e.to_string()
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> ConfigError + '_ {
+    move |source| ConfigError::Io { path: path.to_path_buf(), source }
+}
+
+// 使用中のプロファイルの設定ファイル
+pub fn get_config_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, ConfigError> {
+    let config_dir = profile::active(app_handle)
+        .and_then(|profile| profile::config_dir_of(app_handle, &profile))
+        .map_err(ConfigError::Location)?;
+    fs::create_dir_all(&config_dir).map_err(io_error(&config_dir))?;
+    Ok(config_dir.join("config.json"))
+}
+
+fn migrate_keys_to_camel_case(config: &mut Map<String, Value>) {
+    let keys: Vec<String> = config.keys().filter(|key| key.contains('_')).cloned().collect();
+    for key in keys {
+        let mut camel = String::with_capacity(key.len());
+        let mut upper = false;
+        for c in key.chars() {
+            if c == '_' {
+                upper = true;
+            } else if upper {
+                camel.extend(c.to_uppercase());
+                upper = false;
+            } else {
+                camel.push(c);
+            }
+        }
+        if let Some(value) = config.remove(&key) {
+            config.entry(camel).or_insert(value);
         }
     }
 }
 
-pub fn get_config_path(app_handle: &tauri::AppHandle) -> PathBuf {
-    let config_dir = app_handle.path().app_config_dir().unwrap();
-    fs::create_dir_all(&config_dir).unwrap();
-    config_dir.join("config.json")
+fn reset_max_history(config: &mut Map<String, Value>) {
+    config.insert("maxHistory".to_string(), Value::from(0));
 }
 
-pub fn load_config(app_handle: &tauri::AppHandle) -> Config {
-    let config_path = get_config_path(app_handle);
+fn reset_trash_retention_days(config: &mut Map<String, Value>) {
+    config.insert("trashRetentionDays".to_string(), Value::from(0));
+}
+
+// 設定ファイルの内容を読み込み、古いバージョンの場合は現在の形式に変換する
+// 2つ目の値は変換前のバージョン（変換しなかった場合はNone）
+pub fn parse_config(content: &str) -> Result<(Config, Option<u32>), String> {
+    let mut value: Value = serde_json::from_str(content)
+        .map_err(|e| format!("Failed to parse config: {}", e))?;
+    let map = value.as_object_mut()
+        .ok_or("Failed to parse config: not an object")?;
+    
+    let version = match map.get("version") {
+        Some(version) => version.as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or("Failed to parse config: invalid version")?,
+        None => 1,
+    };
+    
+    // 新しいバージョンのアプリで保存されたファイルは、わかる項目だけを読み込む
+    let migrated_from = if version < CONFIG_VERSION {
+        for migration in MIGRATIONS.iter().skip(version.saturating_sub(1) as usize) {
+            migration(map);
+        }
+        map.insert("version".to_string(), Value::from(CONFIG_VERSION));
+        Some(version)
+    } else {
+        None
+    };
+    
+    let config = serde_json::from_value(value)
+        .map_err(|e| format!("Failed to parse config: {}", e))?;
+    
+    Ok((config, migrated_from))
+}
+
+// 読み込めなかった設定ファイルを上書きしないように、日時を付けた名前で残す
+fn preserve_unreadable(config_path: &Path) -> Result<PathBuf, ConfigError> {
+    let preserved = config_path.with_extension(format!("broken-{}.json", Utc::now().format("%Y%m%d-%H%M%S")));
+    fs::rename(config_path, &preserved).map_err(io_error(config_path))?;
+    Ok(preserved)
+}
+
+// 設定を読み込む（ファイルが無い場合はデフォルト設定を保存する）
+// 設定ファイルを読み書きできない場合はエラーを返し、ファイルには手を付けない
+// 内容を読み込めない場合はファイルを別の名前で残してデフォルト設定を保存し、残した場所をParseエラーで返す
+pub fn load_config(app_handle: &tauri::AppHandle) -> Result<Config, ConfigError> {
+    let config_path = get_config_path(app_handle)?;
     
     if config_path.exists() {
-        match fs::read_to_string(&config_path) {
-            Ok(content) => match serde_json::from_str(&content) {
-                Ok(config) => return config,
-                Err(e) => eprintln!("Failed to parse config: {}", e),
-            },
-            Err(e) => eprintln!("Failed to read config file: {}", e),
+        let content = fs::read_to_string(&config_path).map_err(io_error(&config_path))?;
+        match parse_config(&content) {
+            Ok((config, None)) => return Ok(config),
+            Ok((config, Some(version))) => {
+                // 変換前のファイルは残しておき、現在の形式で保存し直す
+                let previous = config_path.with_extension(format!("v{}.json", version));
+                match fs::copy(&config_path, &previous) {
+                    Ok(_) => {
+                        if let Err(e) = save_config(app_handle, &config) {
+                            eprintln!("Failed to save migrated config: {}", e);
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to back up config before migration: {}", e),
+                }
+                return Ok(config);
+            }
+            Err(message) => {
+                let preserved_path = preserve_unreadable(&config_path)?;
+                save_config(app_handle, &Config::default())?;
+                return Err(ConfigError::Parse { message, preserved_path });
+            }
         }
     }
     
     // デフォルト設定を返す
     let default_config = Config::default();
-    save_config(app_handle, &default_config).unwrap();
-    default_config
+    save_config(app_handle, &default_config)?;
+    Ok(default_config)
 }
 
-pub fn save_config(app_handle: &tauri::AppHandle, config: &Config) -> Result<(), String> {
-    let config_path = get_config_path(app_handle);
+pub fn save_config(app_handle: &tauri::AppHandle, config: &Config) -> Result<(), ConfigError> {
+    let config_path = get_config_path(app_handle)?;
     
-    match serde_json::to_string_pretty(config) {
-        Ok(json) => match fs::write(&config_path, json) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to write config file: {}", e)),
-        },
-        Err(e) => Err(format!("Failed to serialize config: {}", e)),
-    }
-}
\ No newline at end of file
+    let json = serde_json::to_string_pretty(config).map_err(ConfigError::Serialize)?;
+    fs::write(&config_path, json).map_err(io_error(&config_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_keys_to_camel_case_renames_only_snake_case_keys() {
+        let mut map: Map<String, Value> = serde_json::from_str(
+            r#"{"api_key":"k","auto_create_chat":false,"theme":"dark","backup_dir":null}"#,
+        ).unwrap();
+        migrate_keys_to_camel_case(&mut map);
+
+        let mut keys: Vec<&str> = map.keys().map(String::as_str).collect();
+        keys.sort();
+        assert_eq!(keys, ["apiKey", "autoCreateChat", "backupDir", "theme"]);
+        assert_eq!(map["apiKey"], "k");
+        assert_eq!(map["autoCreateChat"], false);
+    }
+
+    #[test]
+    fn migrate_keys_to_camel_case_keeps_existing_camel_case_value() {
+        let mut map: Map<String, Value> = serde_json::from_str(r#"{"apiKey":"new","api_key":"old"}"#).unwrap();
+        migrate_keys_to_camel_case(&mut map);
+
+        assert_eq!(map.len(), 1);
+        assert_eq!(map["apiKey"], "new");
+    }
+
+    #[test]
+    fn parse_config_migrates_v1_to_current_version() {
+        // versionが無く、snake_caseで、項目が足りず、知らない項目があるバージョン1のファイル
+        let v1 = r#"{
+            "api_key": "k",
+            "model": "m",
+            "theme": "dark",
+            "max_history": 5,
+            "auto_create_chat": false,
+            "trash_retention_days": 30,
+            "backup_keep": 3,
+            "embedding_api_key": "e",
+            "unknown_setting": 1
+        }"#;
+        let (config, migrated_from) = parse_config(v1).unwrap();
+
+        assert_eq!(migrated_from, Some(1));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.api_key, "k");
+        assert_eq!(config.model, "m");
+        assert_eq!(config.theme, "dark");
+        assert!(!config.auto_create_chat);
+        assert_eq!(config.backup_keep, 3);
+        assert_eq!(config.embedding_api_key.as_deref(), Some("e"));
+        // 以前は適用されていなかった値はリセットされる
+        assert_eq!(config.max_history, 0);
+        assert_eq!(config.trash_retention_days, 0);
+        // 足りない項目はデフォルト値になる
+        let default = Config::default();
+        assert_eq!(config.auto_title, default.auto_title);
+        assert_eq!(config.backup_interval_hours, default.backup_interval_hours);
+        assert_eq!(config.embedding_model, default.embedding_model);
+        assert_eq!(config.retention_max_age_days, None);
+
+        // 保存し直したファイルはそのまま読み込める
+        let saved = serde_json::to_string_pretty(&config).unwrap();
+        let (reloaded, migrated_from) = parse_config(&saved).unwrap();
+        assert_eq!(migrated_from, None);
+        assert_eq!(serde_json::to_value(&reloaded).unwrap(), serde_json::to_value(&config).unwrap());
+    }
+
+    #[test]
+    fn parse_config_applies_only_newer_migrations() {
+        let v3 = r#"{"version":3,"apiKey":"k","maxHistory":5,"trashRetentionDays":30}"#;
+        let (config, migrated_from) = parse_config(v3).unwrap();
+
+        assert_eq!(migrated_from, Some(3));
+        assert_eq!(config.max_history, 5);
+        assert_eq!(config.trash_retention_days, 0);
+    }
+
+    #[test]
+    fn parse_config_reads_known_keys_of_newer_version() {
+        let future = r#"{"version":99,"apiKey":"x","maxHistory":5,"newThing":1}"#;
+        let (config, migrated_from) = parse_config(future).unwrap();
+
+        assert_eq!(migrated_from, None);
+        assert_eq!(config.version, 99);
+        assert_eq!(config.api_key, "x");
+        assert_eq!(config.max_history, 5);
+    }
+
+    #[test]
+    fn parse_config_rejects_malformed_files() {
+        assert!(parse_config("{ not json").is_err());
+        assert!(parse_config("[]").is_err());
+        assert!(parse_config(r#"{"version":"2"}"#).is_err());
+        assert!(parse_config(r#"{"version":-1}"#).is_err());
+    }
+}
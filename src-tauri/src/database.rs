@@ -1,12 +1,181 @@
 use rusqlite::{params, Connection, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tauri::Manager;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use uuid::Uuid;
 use chrono::Utc;
 use std::fs;
+use crate::transcript::SessionTranscript;
+use crate::embeddings;
+use crate::recall;
+use crate::profile;
+use crate::importers::{self, ImportReport, ImportSource, ImportSummary, ImportedSessionSummary, MergeStrategy};
+use crate::jsonl::{self, JsonlRecord, TransferProgress, PROGRESS_INTERVAL};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 
-#[derive(Debug, Serialize, Deserialize)]
+// エクスポートファイルの形式バージョン
+pub const EXPORT_VERSION: &str = "1.0";
+
+// スキーマのマイグレーション
+// 適用済みのバージョンはPRAGMA user_versionで管理し、未適用のものだけを順に実行する
+const MIGRATIONS: &[&str] = &[
+    // 1: 保持ポリシー用のピン留めとゴミ箱（削除日時）
+    "ALTER TABLE chat_sessions ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE chat_sessions ADD COLUMN deleted_at TEXT;",
+    // 2: メッセージ単位のゴミ箱
+    "ALTER TABLE messages ADD COLUMN deleted_at TEXT;",
+    // 3: フォルダ（入れ子）、タグ、アーカイブ
+    "CREATE TABLE folders (
+         id TEXT PRIMARY KEY,
+         name TEXT NOT NULL,
+         parent_id TEXT REFERENCES folders(id),
+         created_at TEXT NOT NULL
+     );
+     CREATE TABLE tags (
+         id TEXT PRIMARY KEY,
+         name TEXT NOT NULL UNIQUE
+     );
+     CREATE TABLE session_tags (
+         session_id TEXT NOT NULL REFERENCES chat_sessions(id),
+         tag_id TEXT NOT NULL REFERENCES tags(id),
+         PRIMARY KEY (session_id, tag_id)
+     );
+     ALTER TABLE chat_sessions ADD COLUMN folder_id TEXT REFERENCES folders(id);
+     ALTER TABLE chat_sessions ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;",
+    // 4: ユーザーが付けたタイトルを自動生成で上書きしないためのフラグ
+    "ALTER TABLE chat_sessions ADD COLUMN title_edited INTEGER NOT NULL DEFAULT 0;",
+    // 5: セッション内のメッセージを時系列で読み込むためのインデックス
+    "CREATE INDEX IF NOT EXISTS idx_messages_session_timestamp ON messages(session_id, timestamp);",
+    // 6: 時計に依存しないセッション内の連番（既存のメッセージはタイムスタンプ順、同時刻は挿入順に採番する）
+    "ALTER TABLE messages ADD COLUMN seq INTEGER NOT NULL DEFAULT 0;
+     UPDATE messages SET seq = numbered.seq
+     FROM (SELECT id, ROW_NUMBER() OVER (PARTITION BY session_id ORDER BY timestamp, rowid) AS seq FROM messages) AS numbered
+     WHERE messages.id = numbered.id;
+     CREATE INDEX idx_messages_session_seq ON messages(session_id, seq);",
+    // 7: 応答生成中（pending）や失敗（error）を表すメッセージの状態
+    "ALTER TABLE messages ADD COLUMN status TEXT NOT NULL DEFAULT 'complete';",
+    // 8: 応答を生成したモデル（利用状況の集計用）
+    "ALTER TABLE messages ADD COLUMN model TEXT;",
+    // 9: セッションを削除したときにメッセージとタグ付けも削除されるよう、外部キーにON DELETE CASCADEを付けて作り直す
+    // 既存の孤立したメッセージもそのまま移し、repair_orphansで修復する
+    "CREATE TABLE messages_new (
+         id TEXT PRIMARY KEY,
+         session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+         role TEXT NOT NULL,
+         content TEXT NOT NULL,
+         timestamp TEXT NOT NULL,
+         deleted_at TEXT,
+         seq INTEGER NOT NULL DEFAULT 0,
+         status TEXT NOT NULL DEFAULT 'complete',
+         model TEXT
+     );
+     INSERT INTO messages_new (id, session_id, role, content, timestamp, deleted_at, seq, status, model)
+         SELECT id, session_id, role, content, timestamp, deleted_at, seq, status, model FROM messages;
+     DROP TABLE messages;
+     ALTER TABLE messages_new RENAME TO messages;
+     CREATE INDEX idx_messages_session_timestamp ON messages(session_id, timestamp);
+     CREATE INDEX idx_messages_session_seq ON messages(session_id, seq);
+     CREATE TABLE session_tags_new (
+         session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+         tag_id TEXT NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+         PRIMARY KEY (session_id, tag_id)
+     );
+     INSERT INTO session_tags_new (session_id, tag_id) SELECT session_id, tag_id FROM session_tags;
+     DROP TABLE session_tags;
+     ALTER TABLE session_tags_new RENAME TO session_tags;",
+    // 10: セマンティック検索用のメッセージの埋め込み（正規化したf32の配列）
+    "CREATE TABLE message_embeddings (
+         message_id TEXT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+         model TEXT NOT NULL,
+         vector BLOB NOT NULL
+     );",
+    // 11: 思い出しモードで応答に添えた過去のメッセージ（positionはプロンプト内の番号）
+    "CREATE TABLE message_citations (
+         message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+         position INTEGER NOT NULL,
+         cited_message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+         score REAL NOT NULL,
+         PRIMARY KEY (message_id, position)
+     );
+     CREATE INDEX idx_message_citations_cited ON message_citations(cited_message_id);",
+    // 12: 資料（フォルダ内のファイル）のコレクションと、チャットで参照するためのチャンクの埋め込み
+    "CREATE TABLE knowledge_collections (
+         id TEXT PRIMARY KEY,
+         name TEXT NOT NULL,
+         folder TEXT NOT NULL,
+         created_at TEXT NOT NULL,
+         updated_at TEXT NOT NULL
+     );
+     CREATE TABLE knowledge_documents (
+         id TEXT PRIMARY KEY,
+         collection_id TEXT NOT NULL REFERENCES knowledge_collections(id) ON DELETE CASCADE,
+         path TEXT NOT NULL,
+         hash TEXT NOT NULL,
+         ingested_at TEXT NOT NULL,
+         UNIQUE (collection_id, path)
+     );
+     CREATE TABLE knowledge_chunks (
+         id TEXT PRIMARY KEY,
+         document_id TEXT NOT NULL REFERENCES knowledge_documents(id) ON DELETE CASCADE,
+         position INTEGER NOT NULL,
+         content TEXT NOT NULL,
+         model TEXT NOT NULL,
+         vector BLOB NOT NULL
+     );
+     CREATE INDEX idx_knowledge_chunks_document ON knowledge_chunks(document_id);
+     CREATE TABLE session_collections (
+         session_id TEXT NOT NULL REFERENCES chat_sessions(id) ON DELETE CASCADE,
+         collection_id TEXT NOT NULL REFERENCES knowledge_collections(id) ON DELETE CASCADE,
+         PRIMARY KEY (session_id, collection_id)
+     );",
+    // 13: fork_sessionで作成したセッションの分岐元（セッションとメッセージ）
+    "ALTER TABLE chat_sessions ADD COLUMN forked_from_session_id TEXT REFERENCES chat_sessions(id) ON DELETE SET NULL;
+     ALTER TABLE chat_sessions ADD COLUMN forked_from_message_id TEXT REFERENCES messages(id) ON DELETE SET NULL;",
+    // 14: メッセージのスター（ブックマーク）と、自分用のメモ
+    "CREATE TABLE message_bookmarks (
+         message_id TEXT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+         created_at TEXT NOT NULL
+     );
+     CREATE TABLE message_notes (
+         message_id TEXT PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+         content TEXT NOT NULL,
+         updated_at TEXT NOT NULL
+     );",
+    // 15: 埋め込みAPIに拒否されたメッセージ（同じモデルでは計算し直さない）
+    "CREATE TABLE embedding_failures (
+         message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+         model TEXT NOT NULL,
+         error TEXT NOT NULL,
+         failed_at TEXT NOT NULL,
+         PRIMARY KEY (message_id, model)
+     );",
+    // 16: 保持ポリシーでゴミ箱に移動したセッション（ゴミ箱の自動削除の対象外）
+    "ALTER TABLE chat_sessions ADD COLUMN trashed_by_retention INTEGER NOT NULL DEFAULT 0;",
+    // 17: 資料のチャンクはコレクションごとのファイルに移した（knowledge_storeを参照、取り込み直すと作成される）
+    "DROP TABLE IF EXISTS knowledge_chunks;
+     DROP TABLE IF EXISTS knowledge_documents;",
+];
+
+// 孤立したメッセージを入れるために作り直すセッションのタイトル
+const RECOVERED_SESSION_TITLE: &str = "復元されたメッセージ";
+
+// 読み込み用の接続の数
+const READER_COUNT: usize = 4;
+// 他の接続が書き込み中の場合に待つ時間
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+// セッション内の次の連番を求めるサブクエリ（パラメータはセッションID）
+const NEXT_MESSAGE_SEQ: &str = "(SELECT COALESCE(MAX(seq), 0) + 1 FROM messages WHERE session_id = ?)";
+
+// ChatSessionとして読み出す列（session_from_rowと順番を合わせる）
+const SESSION_COLUMNS: &str = "s.id, s.title, s.created_at, s.updated_at, s.pinned, s.archived, s.folder_id,
+     (SELECT json_group_array(t.name) FROM session_tags st JOIN tags t ON t.id = st.tag_id WHERE st.session_id = s.id),
+     s.forked_from_session_id, s.forked_from_message_id";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct ChatSession {
     pub id: String,
     pub title: String,
@@ -14,9 +183,104 @@ pub struct ChatSession {
     pub created_at: String,
     #[serde(rename = "updatedAt")]
     pub updated_at: String,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(rename = "folderId", default)]
+    pub folder_id: Option<String>,
+    // タグ名の一覧
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // 分岐元のセッションとメッセージ（fork_sessionで作成した場合）
+    #[serde(rename = "forkedFromSessionId", default)]
+    pub forked_from_session_id: Option<String>,
+    #[serde(rename = "forkedFromMessageId", default)]
+    pub forked_from_message_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "parentId")]
+    pub parent_id: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "sessionCount")]
+    pub session_count: usize,
+}
+
+// セッション一覧の絞り込み条件
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SessionQuery {
+    // 指定したフォルダのセッションのみ（""の場合はフォルダに入っていないセッション）
+    #[serde(rename = "folderId", default)]
+    pub folder_id: Option<String>,
+    // folder_id指定時にサブフォルダのセッションも含める
+    #[serde(rename = "includeSubfolders", default)]
+    pub include_subfolders: bool,
+    #[serde(rename = "tagId", default)]
+    pub tag_id: Option<String>,
+    // Noneの場合はアーカイブ済みも含める
+    #[serde(default)]
+    pub archived: Option<bool>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+// メッセージのページング条件
+// before/afterにはメッセージIDを指定し、そのメッセージより前/後のメッセージを返す
+// どちらも指定しない場合はlimit件の最新のメッセージを返す（limitもなければすべて）
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MessagePage {
+    #[serde(default)]
+    pub before: Option<String>,
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+// メッセージの状態
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageStatus {
+    #[default]
+    Complete,
+    // アシスタントの応答を生成中
+    Pending,
+    // 応答の生成に失敗した（contentにはエラー内容が入る）
+    Error,
+}
+
+impl MessageStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageStatus::Complete => "complete",
+            MessageStatus::Pending => "pending",
+            MessageStatus::Error => "error",
+        }
+    }
+    
+    fn from_db(value: &str) -> Self {
+        match value {
+            "pending" => MessageStatus::Pending,
+            "error" => MessageStatus::Error,
+            _ => MessageStatus::Complete,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
     pub id: String,
     #[serde(rename = "sessionId")]
@@ -24,6 +288,74 @@ pub struct Message {
     pub role: String,
     pub content: String,
     pub timestamp: String,
+    #[serde(default)]
+    pub status: MessageStatus,
+}
+
+// send_messageで保存された1往復分のメッセージ
+#[derive(Debug, Serialize)]
+pub struct ChatTurn {
+    #[serde(rename = "userMessage")]
+    pub user_message: Message,
+    #[serde(rename = "assistantMessage")]
+    pub assistant_message: Message,
+    // 思い出しモードで応答に添えた過去のメッセージ
+    pub citations: Vec<Citation>,
+}
+
+// 応答の参考にした過去のメッセージ
+#[derive(Debug, Serialize, Clone)]
+pub struct Citation {
+    // プロンプト内の番号（[1]など）
+    pub position: usize,
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "sessionTitle")]
+    pub session_title: String,
+    pub role: String,
+    pub excerpt: String,
+    pub timestamp: String,
+    pub score: f32,
+}
+
+// メッセージのスターとメモ
+#[derive(Debug, Serialize, Default)]
+pub struct MessageAnnotation {
+    pub starred: bool,
+    pub note: Option<String>,
+}
+
+// スターを付けたメッセージ（すべてのセッションから）
+#[derive(Debug, Serialize)]
+pub struct Bookmark {
+    pub message: Message,
+    #[serde(rename = "sessionTitle")]
+    pub session_title: String,
+    pub note: Option<String>,
+    #[serde(rename = "bookmarkedAt")]
+    pub bookmarked_at: String,
+}
+
+// begin_turnの結果
+pub struct PendingTurn {
+    pub user_message: Message,
+    // 応答の保存先となるプレースホルダーのID
+    pub assistant_message_id: String,
+    // モデルに送る履歴（完了したメッセージのみ、今回のユーザーメッセージを含む）
+    pub history: Vec<Message>,
+}
+
+// セッションの統合方法
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionMergeMode {
+    // 統合先のメッセージの後ろに、統合するセッションのメッセージを順に追加する
+    #[default]
+    Append,
+    // すべてのメッセージをタイムスタンプ順に並べ直す
+    Interleave,
 }
 
 // エクスポート/インポート用のデータ構造
@@ -36,18 +368,284 @@ pub struct ExportData {
     pub exported_at: String,
 }
 
+// ゴミ箱内のセッション
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedSession {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: String,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+}
+
+// ゴミ箱内のメッセージ（個別に削除されたもの）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedMessage {
+    pub id: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    #[serde(rename = "sessionTitle")]
+    pub session_title: String,
+    pub role: String,
+    pub content: String,
+    pub timestamp: String,
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Trash {
+    pub sessions: Vec<TrashedSession>,
+    pub messages: Vec<TrashedMessage>,
+}
+
+// メッセージの多いセッション
+#[derive(Debug, Serialize)]
+pub struct SessionSize {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+    pub characters: u64,
+}
+
+// モデルごとの利用状況（モデルを記録する前の応答はmodelがNone）
+#[derive(Debug, Serialize)]
+pub struct ModelUsage {
+    pub model: Option<String>,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+    pub characters: u64,
+    #[serde(rename = "estimatedTokens")]
+    pub estimated_tokens: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseStats {
+    #[serde(rename = "sessionCount")]
+    pub session_count: usize,
+    #[serde(rename = "archivedSessionCount")]
+    pub archived_session_count: usize,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+    #[serde(rename = "trashedSessionCount")]
+    pub trashed_session_count: usize,
+    #[serde(rename = "trashedMessageCount")]
+    pub trashed_message_count: usize,
+    #[serde(rename = "totalCharacters")]
+    pub total_characters: u64,
+    #[serde(rename = "estimatedTokens")]
+    pub estimated_tokens: u64,
+    // データベースファイルのサイズ（バイト）
+    #[serde(rename = "fileSize")]
+    pub file_size: u64,
+    // VACUUMで回収できる未使用領域（バイト）
+    #[serde(rename = "freeSize")]
+    pub free_size: u64,
+    #[serde(rename = "largestSessions")]
+    pub largest_sessions: Vec<SessionSize>,
+    pub models: Vec<ModelUsage>,
+}
+
+// 埋め込みの計算状況
+#[derive(Debug, Serialize)]
+pub struct EmbeddingStatus {
+    pub model: String,
+    // このモデルで埋め込みを計算済みのメッセージ数
+    pub indexed: usize,
+    // 埋め込みAPIに拒否されたため飛ばしたメッセージ数
+    pub failed: usize,
+    // 検索対象になるメッセージ数
+    pub total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SemanticSearchResult {
+    pub message: Message,
+    #[serde(rename = "sessionTitle")]
+    pub session_title: String,
+    // コサイン類似度（1に近いほど意味が近い）
+    pub score: f32,
+}
+
+// 資料のコレクション
+#[derive(Debug, Serialize)]
+pub struct KnowledgeCollection {
+    pub id: String,
+    pub name: String,
+    // 資料を取り込むフォルダ
+    pub folder: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+    #[serde(rename = "documentCount")]
+    pub document_count: usize,
+    #[serde(rename = "chunkCount")]
+    pub chunk_count: usize,
+}
+
+// 取り込み済みの資料（再取り込みで変更を調べるため）
+pub struct KnowledgeDocument {
+    // コレクションのフォルダからの相対パス
+    pub path: String,
+    pub hash: String,
+    // 現在の埋め込みモデルで計算済みかどうか
+    pub up_to_date: bool,
+}
+
+// 質問と意味の近い資料のチャンク
+#[derive(Debug, Serialize, Clone)]
+pub struct KnowledgeChunk {
+    #[serde(rename = "collectionName")]
+    pub collection_name: String,
+    pub path: String,
+    pub content: String,
+    pub score: f32,
+}
+
+// repair_orphansで見つけて修復した不整合の件数
+#[derive(Debug, Serialize)]
+pub struct RepairReport {
+    // 存在しないセッションを参照していたメッセージ（セッションを作り直して残す）
+    #[serde(rename = "orphanMessages")]
+    pub orphan_messages: usize,
+    // 孤立したメッセージのために作り直したセッション
+    #[serde(rename = "recoveredSessions")]
+    pub recovered_sessions: usize,
+    // 存在しないセッションやタグを参照していたタグ付け（削除する）
+    #[serde(rename = "orphanSessionTags")]
+    pub orphan_session_tags: usize,
+    // 存在しないフォルダに入っていたセッション（フォルダから出す）
+    #[serde(rename = "orphanSessionFolders")]
+    pub orphan_session_folders: usize,
+    // 存在しない親フォルダを参照していたフォルダ（最上位に移動する）
+    #[serde(rename = "orphanFolderParents")]
+    pub orphan_folder_parents: usize,
+}
+
+impl RepairReport {
+    pub fn total(&self) -> usize {
+        self.orphan_messages + self.orphan_session_tags + self.orphan_session_folders + self.orphan_folder_parents
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MaintenanceReport {
+    // integrity_checkの結果（問題がなければ["ok"]）
+    pub integrity: Vec<String>,
+    #[serde(rename = "sizeBefore")]
+    pub size_before: u64,
+    #[serde(rename = "sizeAfter")]
+    pub size_after: u64,
+    #[serde(rename = "reclaimedBytes")]
+    pub reclaimed_bytes: u64,
+    // optimizeを実行した全文検索テーブル
+    #[serde(rename = "optimizedFtsTables")]
+    pub optimized_fts_tables: Vec<String>,
+}
+
+// 文字数とUTF-8のバイト数からトークン数を概算する
+// ASCIIは約4文字で1トークン、それ以外（主に3バイトの日本語）は1文字で約1トークンとして数える
+fn estimate_tokens(characters: u64, bytes: u64) -> u64 {
+    let non_ascii = bytes.saturating_sub(characters) / 2;
+    let ascii = characters.saturating_sub(non_ascii);
+    ascii.div_ceil(4) + non_ascii
+}
+
 pub struct Database {
-    conn: Connection,
+    // 書き込み用の接続（WALモードでも同時に書き込めるのは1つだけなので、接続も1つにする）
+    writer: Mutex<Connection>,
+    // 読み込み用の接続（書き込みやエクスポート中も並行して読み込める）
+    readers: Vec<Mutex<Connection>>,
+    next_reader: AtomicUsize,
+    // SQLCipherで暗号化されている場合のパスフレーズ（バックアップを同じ鍵で暗号化するために保持する）
+    passphrase: Option<String>,
+    // ファイルを開けなかったため、終了すると消える一時的なデータベースを使っている
+    in_memory: bool,
 }
 
 impl Database {
-    pub fn new(app_handle: &tauri::AppHandle) -> Result<Self> {
-        let app_data_dir = app_handle.path().app_data_dir().unwrap();
-        std::fs::create_dir_all(&app_data_dir)
+    // 使用中のプロファイルのデータベースファイル
+    fn db_path(app_handle: &tauri::AppHandle) -> Result<PathBuf> {
+        let data_dir = profile::active(app_handle)
+            .and_then(|profile| profile::data_dir_of(app_handle, &profile))
+            .map_err(|e| rusqlite::Error::InvalidPath(PathBuf::from(e)))?;
+        std::fs::create_dir_all(&data_dir)
             .map_err(|e| rusqlite::Error::InvalidPath(PathBuf::from(e.to_string())))?;
         
-        let db_path = app_data_dir.join("chat_history.db");
-        let conn = Connection::open(db_path)?;
+        Ok(data_dir.join("chat_history.db"))
+    }
+    
+    pub fn new(app_handle: &tauri::AppHandle) -> Result<Self> {
+        Self::open(app_handle, None)
+    }
+    
+    // データベースファイルが暗号化されているか（パスフレーズなしで読めるか）を調べる
+    pub fn is_encrypted_file(app_handle: &tauri::AppHandle) -> Result<bool> {
+        Self::is_encrypted_path(&Self::db_path(app_handle)?)
+    }
+    
+    pub fn is_encrypted_path(path: &Path) -> Result<bool> {
+        if !path.exists() {
+            return Ok(false);
+        }
+        
+        let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        match conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+            Ok(_) => Ok(false),
+            Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == rusqlite::ErrorCode::NotADatabase => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+    
+    // 接続を開いて鍵を設定する
+    // パスフレーズが間違っている場合はNotADatabaseエラーになる
+    fn connect(path: &Path, passphrase: Option<&str>) -> Result<Connection> {
+        let conn = Connection::open(path)?;
+        
+        // 鍵の設定は他の操作より先に行う必要がある
+        if let Some(passphrase) = passphrase {
+            conn.pragma_update(None, "key", passphrase)?;
+        }
+        // 鍵が正しいことを確認する
+        conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))?;
+        
+        // 他の接続がロックを持っている場合はしばらく待つ
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // 外部キー制約は接続ごとに有効にする必要がある
+        conn.pragma_update(None, "foreign_keys", true)?;
+        
+        Ok(conn)
+    }
+    
+    // 暗号化されたデータベースはパスフレーズを指定して開く
+    pub fn open(app_handle: &tauri::AppHandle, passphrase: Option<&str>) -> Result<Self> {
+        Self::open_path(&Self::db_path(app_handle)?, passphrase)
+    }
+    
+    // データベースファイルを開けない場合に使う、メモリ上の一時的なデータベースを作成する
+    // 共有キャッシュの接続どうしではWALのように書き込み中のトランザクションを隔離できないため、
+    // 読み込みも書き込み用の接続で行う（readerを参照）
+    pub fn open_in_memory() -> Result<Self> {
+        let uri = format!("file:chat-history-{}?mode=memory&cache=shared", Uuid::new_v4());
+        let mut database = Self::open_path(Path::new(&uri), None)?;
+        database.in_memory = true;
+        
+        Ok(database)
+    }
+    
+    fn open_path(db_path: &Path, passphrase: Option<&str>) -> Result<Self> {
+        let mut conn = Self::connect(db_path, passphrase)?;
+        
+        // WALモードでは書き込み中も他の接続から読み込める
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
         
         // テーブル作成
         conn.execute(
@@ -72,165 +670,2136 @@ impl Database {
             [],
         )?;
         
-        Ok(Self { conn })
-    }
-
-    // チャット履歴をエクスポートする関数
-    pub fn export_data(&self, file_path: &str) -> std::result::Result<(), String> {
-        // すべてのセッションを取得
-        let sessions = self.get_sessions()
-            .map_err(|e| format!("Failed to get sessions: {}", e))?;
-        
-        // すべてのメッセージを取得
-        let mut all_messages = Vec::new();
-        for session in &sessions {
-            let messages = self.get_messages(&session.id)
-                .map_err(|e| format!("Failed to get messages for session {}: {}", session.id, e))?;
-            all_messages.extend(messages);
-        }
+        Self::migrate(&mut conn)?;
         
-        // エクスポートデータを作成
-        let export_data = ExportData {
-            sessions,
-            messages: all_messages,
-            version: "1.0".to_string(),
-            exported_at: Utc::now().to_rfc3339(),
-        };
+        // 読み込み用の接続は誤って書き込まないようにする
+        let readers = (0..READER_COUNT)
+            .map(|_| {
+                let reader = Self::connect(db_path, passphrase)?;
+                reader.pragma_update(None, "query_only", true)?;
+                Ok(Mutex::new(reader))
+            })
+            .collect::<Result<Vec<_>>>()?;
         
-        // JSONに変換
-        let json = serde_json::to_string_pretty(&export_data)
-            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+        Ok(Self {
+            writer: Mutex::new(conn),
+            readers,
+            next_reader: AtomicUsize::new(0),
+            passphrase: passphrase.map(|p| p.to_string()),
+            in_memory: false,
+        })
+    }
+    
+    fn writer(&self) -> MutexGuard<'_, Connection> {
+        self.writer.lock().unwrap()
+    }
+    
+    // 空いている読み込み用の接続を返す（すべて使用中の場合は順番に割り当てて待つ）
+    // 同じスレッドで2つ目の接続を取るとデッドロックする可能性があるため、取得した接続は使い回す
+    // メモリ上のデータベースでは、コミット前の変更が見えないように書き込み用の接続を返す
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        if self.in_memory {
+            return self.writer();
+        }
         
-        // ファイルに書き込み
-        fs::write(file_path, json)
-            .map_err(|e| format!("Failed to write file: {}", e))?;
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.readers.len() {
+            if let Ok(conn) = self.readers[(start + i) % self.readers.len()].try_lock() {
+                return conn;
+            }
+        }
         
-        Ok(())
+        self.readers[start % self.readers.len()].lock().unwrap()
     }
     
-    // チャット履歴をインポートする関数
-    pub fn import_data(&mut self, file_path: &str) -> std::result::Result<(), String> {
-        // ファイルを読み込み
-        let json = fs::read_to_string(file_path)
-            .map_err(|e| format!("Failed to read file: {}", e))?;
+    pub fn is_in_memory(&self) -> bool {
+        self.in_memory
+    }
+    
+    pub fn is_encrypted(&self) -> bool {
+        self.passphrase.is_some()
+    }
+    
+    pub fn passphrase(&self) -> Option<&str> {
+        self.passphrase.as_deref()
+    }
+    
+    // パスフレーズを設定・変更・解除し、開き直したデータベースを返す
+    // 暗号化の有無が変わる場合は、sqlcipher_exportで別ファイルに書き出してから置き換える
+    // 新しいパスフレーズで開けることを整合性チェックで確かめてから返す
+    pub fn change_passphrase(self, app_handle: &tauri::AppHandle, passphrase: Option<&str>) -> std::result::Result<Self, String> {
+        let was_encrypted = self.is_encrypted();
+        if !was_encrypted && passphrase.is_none() {
+            return Ok(self);
+        }
         
-        // JSONをパース
-        let import_data: ExportData = serde_json::from_str(&json)
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+        // 読み込み用の接続は古い鍵のままになるため、先に閉じて最後に開き直す
+        let Self { writer, readers, .. } = self;
+        drop(readers);
+        let conn = writer.into_inner().unwrap();
         
-        // トランザクションを開始
-        let tx = self.conn.transaction()
-            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        let db_path = Self::db_path(app_handle).map_err(|e| e.to_string())?;
         
-        // セッションをインポート
-        for session in &import_data.sessions {
-            tx.execute(
-                "INSERT OR REPLACE INTO chat_sessions (id, title, created_at, updated_at) VALUES (?, ?, ?, ?)",
-                params![session.id, session.title, session.created_at, session.updated_at],
-            ).map_err(|e| format!("Failed to insert session: {}", e))?;
+        // 暗号化済みのデータベースは鍵をかけ直すだけでよい
+        // rekeyはページを書き換えられなくても成功を返すことがあるため、新しい鍵で読めるか確かめる
+        if let (true, Some(passphrase)) = (was_encrypted, passphrase) {
+            conn.pragma_update(None, "rekey", passphrase).map_err(|e| e.to_string())?;
+            drop(conn);
+            Self::check_integrity(&db_path, Some(passphrase))
+                .map_err(|e| format!("新しいパスフレーズでデータベースを開けませんでした: {}", e))?;
+            return Self::open(app_handle, Some(passphrase)).map_err(|e| e.to_string());
         }
         
-        // メッセージをインポート
-        for message in &import_data.messages {
-            tx.execute(
-                "INSERT OR REPLACE INTO messages (id, session_id, role, content, timestamp) VALUES (?, ?, ?, ?, ?)",
-                params![message.id, message.session_id, message.role, message.content, message.timestamp],
-            ).map_err(|e| format!("Failed to insert message: {}", e))?;
+        let converted_path = db_path.with_extension("db.converting");
+        if converted_path.exists() {
+            fs::remove_file(&converted_path).map_err(|e| e.to_string())?;
         }
         
-        // トランザクションをコミット
-        tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        // 空のKEYを指定すると暗号化されていないデータベースとして書き出される
+        // テーブルは親子関係と無関係な順に書き出されるため、外部キーの検査は止めておく
+        let export = || -> Result<()> {
+            conn.pragma_update(None, "foreign_keys", false)?;
+            let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            conn.execute(
+                "ATTACH DATABASE ?1 AS converted KEY ?2",
+                params![converted_path.to_string_lossy(), passphrase.unwrap_or("")],
+            )?;
+            conn.query_row("SELECT sqlcipher_export('converted')", [], |_| Ok(()))?;
+            conn.pragma_update(Some(rusqlite::DatabaseName::Attached("converted")), "user_version", version)?;
+            conn.execute("DETACH DATABASE converted", [])
+                .map(|_| ())
+        };
+        export().map_err(|e| e.to_string())?;
         
-        Ok(())
+        // 最後の接続を閉じるとWALの内容が書き戻されるので、その後でファイルを置き換える
+        // 書き出したファイルを新しいパスフレーズで読めない場合は、元のファイルを残す
+        drop(conn);
+        if let Err(e) = Self::check_integrity(&converted_path, passphrase) {
+            let _ = fs::remove_file(&converted_path);
+            return Err(format!("書き出したデータベースを新しいパスフレーズで開けませんでした: {}", e));
+        }
+        fs::rename(&converted_path, &db_path).map_err(|e| e.to_string())?;
+        
+        Self::open(app_handle, passphrase).map_err(|e| e.to_string())
     }
     
-    pub fn create_session(&self, title: &str) -> Result<String> {
-        let id = Uuid::new_v4().to_string();
-        let now = Utc::now().to_rfc3339();
+    // オンラインバックアップAPIでデータベースを別ファイルに書き出す
+    // 暗号化されている場合はバックアップも同じパスフレーズで暗号化される
+    pub fn backup_to(&self, path: &Path) -> Result<()> {
+        let mut dest = Connection::open(path)?;
+        if let Some(passphrase) = &self.passphrase {
+            dest.pragma_update(None, "key", passphrase)?;
+        }
         
-        self.conn.execute(
-            "INSERT INTO chat_sessions (id, title, created_at, updated_at) VALUES (?, ?, ?, ?)",
-            params![id, title, now, now],
-        )?;
+        let conn = self.reader();
+        let backup = rusqlite::backup::Backup::new(&conn, &mut dest)?;
+        backup.run_to_completion(100, Duration::from_millis(10), None)
+    }
+    
+    // PRAGMA integrity_checkでファイルが壊れていないか確認する
+    pub fn check_integrity(path: &Path, passphrase: Option<&str>) -> std::result::Result<(), String> {
+        let conn = Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Failed to open database: {}", e))?;
+        if let Some(passphrase) = passphrase {
+            conn.pragma_update(None, "key", passphrase)
+                .map_err(|e| format!("Failed to open database: {}", e))?;
+        }
         
-        Ok(id)
+        let mut stmt = conn.prepare("PRAGMA integrity_check")
+            .map_err(|e| format!("Failed to check integrity: {}", e))?;
+        let problems = stmt.query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| format!("Failed to check integrity: {}", e))?
+            .collect::<Result<Vec<_>>>()
+            .map_err(|e| format!("Failed to check integrity: {}", e))?;
+        
+        if problems.len() == 1 && problems[0] == "ok" {
+            Ok(())
+        } else {
+            Err(format!("整合性チェックに失敗しました: {}", problems.join(", ")))
+        }
     }
     
-    pub fn add_message(&self, session_id: &str, role: &str, content: &str) -> Result<String> {
-        let id = Uuid::new_v4().to_string();
-        let now = Utc::now().to_rfc3339();
+    // バックアップからデータベースを復元し、開き直したデータベースを返す
+    // 復元したファイルを開けなかった場合は元のファイルに戻す（呼び出し側で開き直す）
+    pub fn restore_from(self, app_handle: &tauri::AppHandle, path: &Path, passphrase: Option<&str>) -> std::result::Result<Self, String> {
+        Self::check_integrity(path, passphrase)?;
         
-        self.conn.execute(
-            "INSERT INTO messages (id, session_id, role, content, timestamp) VALUES (?, ?, ?, ?, ?)",
-            params![id, session_id, role, content, now],
-        )?;
+        let db_path = Self::db_path(app_handle).map_err(|e| e.to_string())?;
+        let restoring_path = db_path.with_extension("db.restoring");
+        let previous_path = db_path.with_extension("db.before-restore");
         
-        // セッションの更新日時を更新
-        self.conn.execute(
-            "UPDATE chat_sessions SET updated_at = ? WHERE id = ?",
-            params![now, session_id],
-        )?;
+        fs::copy(path, &restoring_path)
+            .map_err(|e| format!("Failed to copy backup: {}", e))?;
         
-        Ok(id)
+        // 元の接続を閉じてから、現在のファイルを退避して置き換える
+        drop(self);
+        fs::rename(&db_path, &previous_path)
+            .map_err(|e| format!("Failed to replace database: {}", e))?;
+        // 置き換えられなかった場合は、退避したファイルを元に戻す（戻さないと空のデータベースが作成される）
+        if let Err(e) = fs::rename(&restoring_path, &db_path) {
+            fs::rename(&previous_path, &db_path)
+                .map_err(|rollback| format!("Failed to replace database: {}; failed to roll back restore: {}", e, rollback))?;
+            let _ = fs::remove_file(&restoring_path);
+            return Err(format!("Failed to replace database: {}", e));
+        }
+        
+        match Self::open(app_handle, passphrase) {
+            Ok(database) => {
+                let _ = fs::remove_file(&previous_path);
+                Ok(database)
+            }
+            Err(e) => {
+                fs::rename(&previous_path, &db_path)
+                    .map_err(|e| format!("Failed to roll back restore: {}", e))?;
+                Err(format!("Failed to open restored database: {}", e))
+            }
+        }
     }
     
-    pub fn get_sessions(&self) -> Result<Vec<ChatSession>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, title, created_at, updated_at FROM chat_sessions ORDER BY updated_at DESC"
+    fn file_size(conn: &Connection) -> Result<u64> {
+        conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )
+    }
+    
+    pub fn stats(&self) -> Result<DatabaseStats> {
+        let conn = self.reader();
+        let count = |sql: &str| -> Result<usize> { conn.query_row(sql, [], |row| row.get(0)) };
+        
+        let (total_characters, total_bytes): (u64, u64) = conn.query_row(
+            "SELECT COALESCE(SUM(LENGTH(content)), 0), COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0)
+             FROM messages WHERE deleted_at IS NULL",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )?;
         
-        let sessions = stmt.query_map([], |row| {
-            Ok(ChatSession {
+        let free_size: u64 = conn.query_row(
+            "SELECT freelist_count * page_size FROM pragma_freelist_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+        
+        let mut stmt = conn.prepare(
+            "SELECT s.id, s.title, COUNT(m.id), COALESCE(SUM(LENGTH(m.content)), 0)
+             FROM chat_sessions s
+             JOIN messages m ON m.session_id = s.id AND m.deleted_at IS NULL
+             WHERE s.deleted_at IS NULL
+             GROUP BY s.id
+             ORDER BY COUNT(m.id) DESC
+             LIMIT 10"
+        )?;
+        let largest_sessions = stmt.query_map([], |row| {
+            Ok(SessionSize {
                 id: row.get(0)?,
                 title: row.get(1)?,
-                created_at: row.get(2)?,
-                updated_at: row.get(3)?,
+                message_count: row.get(2)?,
+                characters: row.get(3)?,
             })
         })?
         .collect::<Result<Vec<_>>>()?;
         
-        Ok(sessions)
-    }
-    
-    pub fn get_messages(&self, session_id: &str) -> Result<Vec<Message>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, session_id, role, content, timestamp FROM messages
-             WHERE session_id = ? ORDER BY timestamp ASC"
+        let mut stmt = conn.prepare(
+            "SELECT model, COUNT(*), COALESCE(SUM(LENGTH(content)), 0), COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0)
+             FROM messages
+             WHERE role = 'assistant' AND status = 'complete' AND deleted_at IS NULL
+             GROUP BY model
+             ORDER BY COUNT(*) DESC"
         )?;
-        
-        let messages = stmt.query_map(params![session_id], |row| {
-            Ok(Message {
-                id: row.get(0)?,
-                session_id: row.get(1)?,
-                role: row.get(2)?,
-                content: row.get(3)?,
-                timestamp: row.get(4)?,
+        let models = stmt.query_map([], |row| {
+            let characters: u64 = row.get(2)?;
+            Ok(ModelUsage {
+                model: row.get(0)?,
+                message_count: row.get(1)?,
+                characters,
+                estimated_tokens: estimate_tokens(characters, row.get(3)?),
             })
         })?
         .collect::<Result<Vec<_>>>()?;
         
-        Ok(messages)
+        Ok(DatabaseStats {
+            session_count: count("SELECT COUNT(*) FROM chat_sessions WHERE deleted_at IS NULL")?,
+            archived_session_count: count("SELECT COUNT(*) FROM chat_sessions WHERE deleted_at IS NULL AND archived = 1")?,
+            message_count: count("SELECT COUNT(*) FROM messages WHERE deleted_at IS NULL")?,
+            trashed_session_count: count("SELECT COUNT(*) FROM chat_sessions WHERE deleted_at IS NOT NULL")?,
+            trashed_message_count: count("SELECT COUNT(*) FROM messages WHERE deleted_at IS NOT NULL")?,
+            total_characters,
+            estimated_tokens: estimate_tokens(total_characters, total_bytes),
+            file_size: Self::file_size(&conn)?,
+            free_size,
+            largest_sessions,
+            models,
+        })
     }
     
-    pub fn delete_session(&mut self, session_id: &str) -> Result<()> {
-        // トランザクションを開始
-        let tx = self.conn.transaction()?;
+    // 整合性チェック、VACUUM、ANALYZE、全文検索インデックスの最適化を行う
+    // 整合性チェックで問題が見つかった場合は、それ以降の処理を行わない
+    pub fn run_maintenance(&self) -> Result<MaintenanceReport> {
+        // VACUUMは書き込みになるため書き込み用の接続で行う
+        let conn = self.writer();
+        let size_before = Self::file_size(&conn)?;
         
-        // まず関連するメッセージを削除
-        tx.execute(
-            "DELETE FROM messages WHERE session_id = ?",
-            params![session_id],
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let integrity = stmt.query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        if !(integrity.len() == 1 && integrity[0] == "ok") {
+            return Ok(MaintenanceReport {
+                integrity,
+                size_before,
+                size_after: size_before,
+                reclaimed_bytes: 0,
+                optimized_fts_tables: Vec::new(),
+            });
+        }
+        
+        // FTS5の仮想テーブルを探して最適化する
+        let mut stmt = conn.prepare(
+            "SELECT name FROM sqlite_master
+             WHERE type = 'table' AND sql LIKE 'CREATE VIRTUAL TABLE%USING fts5%'"
         )?;
+        let fts_tables = stmt.query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+        for table in &fts_tables {
+            let table = table.replace('"', "\"\"");
+            conn.execute(&format!("INSERT INTO \"{0}\"(\"{0}\") VALUES ('optimize')", table), [])?;
+        }
         
-        // 次にセッション自体を削除
-        tx.execute(
-            "DELETE FROM chat_sessions WHERE id = ?",
-            params![session_id],
+        conn.execute_batch("VACUUM; ANALYZE;")?;
+        
+        let size_after = Self::file_size(&conn)?;
+        
+        Ok(MaintenanceReport {
+            integrity,
+            size_before,
+            size_after,
+            reclaimed_bytes: size_before.saturating_sub(size_after),
+            optimized_fts_tables: fts_tables,
+        })
+    }
+    
+    // 未適用のマイグレーションを実行する
+    fn migrate(conn: &mut Connection) -> Result<()> {
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        
+        // テーブルを作り直すマイグレーションがあるため、適用中は外部キーの検査を止める
+        // （トランザクション内では切り替えられない）
+        conn.pragma_update(None, "foreign_keys", false)?;
+        
+        for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(sql)?;
+            tx.pragma_update(None, "user_version", i + 1)?;
+            tx.commit()?;
+        }
+        
+        conn.pragma_update(None, "foreign_keys", true)?;
+        
+        Ok(())
+    }
+    
+    // 外部キーが有効になる前に作られた不整合を修復する
+    // 孤立したメッセージは削除せず、同じIDのセッションを作り直してその中に残す
+    pub fn repair_orphans(&self) -> Result<RepairReport> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        let orphan_messages = tx.query_row(
+            "SELECT COUNT(*) FROM messages WHERE session_id NOT IN (SELECT id FROM chat_sessions)",
+            [],
+            |row| row.get(0),
+        )?;
+        let recovered_sessions = tx.execute(
+            "INSERT INTO chat_sessions (id, title, created_at, updated_at)
+             SELECT session_id, ?, MIN(timestamp), MAX(timestamp)
+             FROM messages
+             WHERE session_id NOT IN (SELECT id FROM chat_sessions)
+             GROUP BY session_id",
+            params![RECOVERED_SESSION_TITLE],
+        )?;
+        
+        let orphan_session_tags = tx.execute(
+            "DELETE FROM session_tags
+             WHERE session_id NOT IN (SELECT id FROM chat_sessions)
+                OR tag_id NOT IN (SELECT id FROM tags)",
+            [],
+        )?;
+        
+        let orphan_session_folders = tx.execute(
+            "UPDATE chat_sessions SET folder_id = NULL
+             WHERE folder_id IS NOT NULL AND folder_id NOT IN (SELECT id FROM folders)",
+            [],
+        )?;
+        let orphan_folder_parents = tx.execute(
+            "UPDATE folders SET parent_id = NULL
+             WHERE parent_id IS NOT NULL AND parent_id NOT IN (SELECT id FROM folders)",
+            [],
         )?;
         
-        // トランザクションをコミット
         tx.commit()?;
         
-        Ok(())
+        Ok(RepairReport {
+            orphan_messages,
+            recovered_sessions,
+            orphan_session_tags,
+            orphan_session_folders,
+            orphan_folder_parents,
+        })
     }
-}
\ No newline at end of file
+    
+    // 保持ポリシーを適用し、ゴミ箱に移動したセッション数を返す
+    // ピン留めされたセッションは対象外。max_sessionsが0の場合は件数による制限を行わない
+    pub fn apply_retention(&self, max_sessions: usize, max_age_days: Option<u32>) -> Result<usize> {
+        let now = Utc::now();
+        let deleted_at = now.to_rfc3339();
+        let mut pruned = 0;
+        let conn = self.writer();
+        
+        // 一定期間更新されていないセッション
+        if let Some(days) = max_age_days {
+            let cutoff = (now - chrono::Duration::days(days as i64)).to_rfc3339();
+            pruned += conn.execute(
+                "UPDATE chat_sessions SET deleted_at = ?, trashed_by_retention = 1
+                 WHERE deleted_at IS NULL AND pinned = 0 AND updated_at < ?",
+                params![deleted_at, cutoff],
+            )?;
+        }
+        
+        // 上限を超えた古いセッション
+        if max_sessions > 0 {
+            pruned += conn.execute(
+                "UPDATE chat_sessions SET deleted_at = ?, trashed_by_retention = 1
+                 WHERE id IN (
+                     SELECT id FROM chat_sessions
+                     WHERE deleted_at IS NULL AND pinned = 0
+                     ORDER BY updated_at DESC
+                     LIMIT -1 OFFSET ?
+                 )",
+                params![deleted_at, max_sessions as i64],
+            )?;
+        }
+        
+        Ok(pruned)
+    }
+
+    // チャット履歴をエクスポートする関数（session_idsを指定した場合はそのセッションのみ）
+    pub fn export_data(&self, file_path: &str, session_ids: Option<&[String]>) -> std::result::Result<(), String> {
+        // 対象のセッションを取得
+        let sessions = self.get_sessions_filtered(session_ids)
+            .map_err(|e| format!("Failed to get sessions: {}", e))?;
+        
+        // すべてのメッセージを取得
+        let mut all_messages = Vec::new();
+        for session in &sessions {
+            let messages = self.get_messages(&session.id)
+                .map_err(|e| format!("Failed to get messages for session {}: {}", session.id, e))?;
+            all_messages.extend(messages);
+        }
+        
+        // エクスポートデータを作成
+        let export_data = ExportData {
+            sessions,
+            messages: all_messages,
+            version: EXPORT_VERSION.to_string(),
+            exported_at: Utc::now().to_rfc3339(),
+        };
+        
+        // JSONに変換
+        let json = serde_json::to_string_pretty(&export_data)
+            .map_err(|e| format!("Failed to serialize data: {}", e))?;
+        
+        // ファイルに書き込み
+        fs::write(file_path, json)
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        
+        Ok(())
+    }
+    
+    // Markdown/HTMLエクスポート用にセッションとメッセージをまとめて取得する
+    pub fn get_transcripts(&self, session_ids: Option<&[String]>) -> Result<Vec<SessionTranscript>> {
+        let sessions = self.get_sessions_filtered(session_ids)?;
+        
+        let mut transcripts = Vec::with_capacity(sessions.len());
+        for session in sessions {
+            let messages = self.get_messages(&session.id)?;
+            let notes = self.get_session_notes(&session.id)?;
+            let models = self.get_session_models(&session.id)?;
+            transcripts.push(SessionTranscript { session, messages, notes, models });
+        }
+        
+        Ok(transcripts)
+    }
+    
+    // 指定されたIDのセッションのみを返す（Noneの場合はすべて）
+    fn get_sessions_filtered(&self, session_ids: Option<&[String]>) -> Result<Vec<ChatSession>> {
+        let sessions = self.get_sessions()?;
+        
+        Ok(match session_ids {
+            Some(ids) => sessions.into_iter().filter(|s| ids.contains(&s.id)).collect(),
+            None => sessions,
+        })
+    }
+    
+    // チャット履歴をインポートする関数
+    // sourceがNoneの場合はファイルの内容から形式を判定する
+    // dry_runの場合も同じ処理をトランザクション内で実行し、コミットせずに結果だけを報告する
+    pub fn import_data(
+        &self,
+        file_path: &str,
+        source: Option<ImportSource>,
+        strategy: MergeStrategy,
+        dry_run: bool,
+        progress: &mut dyn FnMut(TransferProgress),
+    ) -> std::result::Result<ImportReport, String> {
+        // JSON Lines形式は1行ずつ読み込む
+        let is_jsonl = match source {
+            Some(source) => source == ImportSource::Jsonl,
+            None => jsonl::is_jsonl_file(file_path)?,
+        };
+        if is_jsonl {
+            return self.import_jsonl(file_path, strategy, dry_run, progress);
+        }
+        
+        // ファイルを読み込み、共通形式に変換して検証
+        let (source, import_data, skipped_messages) = importers::load(file_path, source)?;
+        importers::validate(&import_data)?;
+        
+        let mut report = importers::build_report(source, file_path, dry_run, strategy, &import_data, skipped_messages);
+        
+        // トランザクションを開始
+        let mut conn = self.writer();
+        let tx = conn.transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        
+        report.summary = Self::merge_import_data(&tx, &import_data, strategy)?;
+        
+        // ドライランの場合はコミットせずにロールバックする
+        if dry_run {
+            return Ok(report);
+        }
+        
+        // トランザクションをコミット
+        tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        
+        Ok(report)
+    }
+    
+    // インポートデータを既存のデータとマージする
+    fn merge_import_data(
+        tx: &rusqlite::Transaction,
+        data: &ExportData,
+        strategy: MergeStrategy,
+    ) -> std::result::Result<ImportSummary, String> {
+        let mut summary = ImportSummary::default();
+        // インポートファイル上のセッションIDから、実際に書き込んだセッションIDへの対応（スキップした場合はNone）
+        let mut session_ids: HashMap<String, Option<String>> = HashMap::new();
+        
+        // セッションをインポート
+        for session in &data.sessions {
+            let target_id = Self::merge_session(tx, session, strategy, &mut summary)?;
+            session_ids.insert(session.id.clone(), target_id);
+        }
+        
+        // メッセージをインポート
+        for message in &data.messages {
+            Self::merge_message(tx, message, &session_ids, strategy, &mut summary)?;
+        }
+        
+        Ok(summary)
+    }
+    
+    fn row_exists(tx: &rusqlite::Transaction, sql: &str, id: &str) -> std::result::Result<bool, String> {
+        tx.query_row(sql, params![id], |row| row.get(0))
+            .map_err(|e| format!("Failed to query existing rows: {}", e))
+    }
+    
+    // セッションを1件マージし、書き込んだセッションIDを返す（既存のセッションをスキップした場合はNone）
+    fn merge_session(
+        tx: &rusqlite::Transaction,
+        session: &ChatSession,
+        strategy: MergeStrategy,
+        summary: &mut ImportSummary,
+    ) -> std::result::Result<Option<String>, String> {
+        let exists = Self::row_exists(tx, "SELECT EXISTS(SELECT 1 FROM chat_sessions WHERE id = ?)", &session.id)?;
+        
+        match (exists, strategy) {
+            (true, MergeStrategy::Skip) => {
+                summary.sessions_skipped += 1;
+                Ok(None)
+            }
+            (true, MergeStrategy::Overwrite) => {
+                // ゴミ箱にあるセッションは上書きして元に戻す
+                tx.execute(
+                    "UPDATE chat_sessions SET title = ?, created_at = ?, updated_at = ?, pinned = ?, archived = ?,
+                         deleted_at = NULL, trashed_by_retention = 0
+                     WHERE id = ?",
+                    params![session.title, session.created_at, session.updated_at, session.pinned, session.archived, session.id],
+                ).map_err(|e| format!("Failed to update session: {}", e))?;
+                summary.sessions_updated += 1;
+                Ok(Some(session.id.clone()))
+            }
+            (exists, _) => {
+                let id = if exists { Uuid::new_v4().to_string() } else { session.id.clone() };
+                tx.execute(
+                    "INSERT INTO chat_sessions (id, title, created_at, updated_at, pinned, archived) VALUES (?, ?, ?, ?, ?, ?)",
+                    params![id, session.title, session.created_at, session.updated_at, session.pinned, session.archived],
+                ).map_err(|e| format!("Failed to insert session: {}", e))?;
+                summary.sessions_inserted += 1;
+                Ok(Some(id))
+            }
+        }
+    }
+    
+    // メッセージを1件マージする
+    fn merge_message(
+        tx: &rusqlite::Transaction,
+        message: &Message,
+        session_ids: &HashMap<String, Option<String>>,
+        strategy: MergeStrategy,
+        summary: &mut ImportSummary,
+    ) -> std::result::Result<(), String> {
+        // インポートファイルにもローカルにも存在しないセッションを参照するメッセージはスキップ
+        let target_session_id = match session_ids.get(&message.session_id) {
+            Some(Some(id)) => id.clone(),
+            // スキップした既存のセッションには、メッセージも追加しない
+            Some(None) => {
+                summary.messages_skipped += 1;
+                return Ok(());
+            }
+            None if Self::row_exists(tx, "SELECT EXISTS(SELECT 1 FROM chat_sessions WHERE id = ?)", &message.session_id)? => {
+                message.session_id.clone()
+            }
+            None => {
+                summary.orphan_messages += 1;
+                return Ok(());
+            }
+        };
+        
+        // コピーとして作成したセッションのメッセージは、常に新しいIDで作成する
+        let session_copied = target_session_id != message.session_id;
+        let exists = !session_copied
+            && Self::row_exists(tx, "SELECT EXISTS(SELECT 1 FROM messages WHERE id = ?)", &message.id)?;
+        
+        match (exists, strategy) {
+            (true, MergeStrategy::Skip) => {
+                summary.messages_skipped += 1;
+            }
+            (true, MergeStrategy::Overwrite) => {
+                // 別のセッションに移る場合は移動先の末尾の連番を振り直す
+                tx.execute(
+                    "UPDATE messages SET
+                         seq = CASE WHEN session_id = ?1 THEN seq
+                                    ELSE (SELECT COALESCE(MAX(seq), 0) + 1 FROM messages WHERE session_id = ?1) END,
+                         session_id = ?1, role = ?2, content = ?3, timestamp = ?4, status = ?5, deleted_at = NULL
+                     WHERE id = ?6",
+                    params![target_session_id, message.role, message.content, message.timestamp, message.status.as_str(), message.id],
+                ).map_err(|e| format!("Failed to update message: {}", e))?;
+                // 内容が変わった可能性があるので埋め込みは計算し直す
+                tx.execute("DELETE FROM message_embeddings WHERE message_id = ?", params![message.id])
+                    .map_err(|e| format!("Failed to update message: {}", e))?;
+                summary.messages_updated += 1;
+            }
+            (exists, _) => {
+                let id = if exists || session_copied { Uuid::new_v4().to_string() } else { message.id.clone() };
+                tx.execute(
+                    &format!(
+                        "INSERT INTO messages (id, session_id, role, content, timestamp, status, seq) VALUES (?, ?, ?, ?, ?, ?, {})",
+                        NEXT_MESSAGE_SEQ
+                    ),
+                    params![id, target_session_id, message.role, message.content, message.timestamp, message.status.as_str(), target_session_id],
+                ).map_err(|e| format!("Failed to insert message: {}", e))?;
+                summary.messages_inserted += 1;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    // JSON Lines形式でエクスポートする
+    // セッションとメッセージを1つのクエリで順に読み出し、1行ずつ書き出すためメモリ使用量は一定
+    pub fn export_jsonl(
+        &self,
+        file_path: &str,
+        session_ids: Option<&[String]>,
+        progress: &mut dyn FnMut(TransferProgress),
+    ) -> std::result::Result<(), String> {
+        // 読み込み用の接続を最後まで使うため、エクスポート中もメッセージの送信は妨げない
+        let conn = self.reader();
+        // 選択したセッションだけを書き出す条件
+        let session_filter = match session_ids {
+            Some(ids) => format!(" AND s.id IN ({})", vec!["?"; ids.len()].join(", ")),
+            None => String::new(),
+        };
+        let session_params = || rusqlite::params_from_iter(session_ids.unwrap_or_default());
+        // 進捗の合計は、実際に書き出す（ゴミ箱にない、選択したセッションの）メッセージの数
+        let count_sql = format!(
+            "SELECT COUNT(*) FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.deleted_at IS NULL AND s.deleted_at IS NULL{}",
+            session_filter
+        );
+        let total: u64 = conn.query_row(&count_sql, session_params(), |row| row.get(0))
+            .map_err(|e| format!("Failed to count messages: {}", e))?;
+        
+        let file = fs::File::create(file_path)
+            .map_err(|e| format!("Failed to create file: {}", e))?;
+        let mut writer = BufWriter::new(file);
+        
+        let write_record = |writer: &mut BufWriter<fs::File>, record: &JsonlRecord| -> std::result::Result<(), String> {
+            serde_json::to_writer(&mut *writer, record)
+                .map_err(|e| format!("Failed to serialize data: {}", e))?;
+            writer.write_all(b"\n")
+                .map_err(|e| format!("Failed to write file: {}", e))
+        };
+        
+        write_record(&mut writer, &JsonlRecord::Header {
+            version: EXPORT_VERSION.to_string(),
+            exported_at: Utc::now().to_rfc3339(),
+        })?;
+        
+        // メッセージのないセッションも出力するためLEFT JOINする
+        let mut stmt = conn.prepare(&format!(
+            "SELECT s.id, s.title, s.created_at, s.updated_at, m.id, m.role, m.content, m.timestamp, s.pinned, s.archived, m.status
+             FROM chat_sessions s
+             LEFT JOIN messages m ON m.session_id = s.id AND m.deleted_at IS NULL
+             WHERE s.deleted_at IS NULL{}
+             ORDER BY s.updated_at DESC, s.id, m.seq ASC",
+            session_filter
+        )).map_err(|e| format!("Failed to prepare query: {}", e))?;
+        let mut rows = stmt.query(session_params())
+            .map_err(|e| format!("Failed to query messages: {}", e))?;
+        
+        let mut current_session: Option<String> = None;
+        let mut processed: u64 = 0;
+        
+        while let Some(row) = rows.next().map_err(|e| format!("Failed to read row: {}", e))? {
+            let session_id: String = row.get(0).map_err(|e| e.to_string())?;
+            
+            // セッションが切り替わったらセッション行を書き出す
+            if current_session.as_deref() != Some(session_id.as_str()) {
+                write_record(&mut writer, &JsonlRecord::Session(ChatSession {
+                    id: session_id.clone(),
+                    title: row.get(1).map_err(|e| e.to_string())?,
+                    created_at: row.get(2).map_err(|e| e.to_string())?,
+                    updated_at: row.get(3).map_err(|e| e.to_string())?,
+                    pinned: row.get(8).map_err(|e| e.to_string())?,
+                    archived: row.get(9).map_err(|e| e.to_string())?,
+                    ..Default::default()
+                }))?;
+                current_session = Some(session_id.clone());
+            }
+            
+            let message_id: Option<String> = row.get(4).map_err(|e| e.to_string())?;
+            if let Some(message_id) = message_id {
+                write_record(&mut writer, &JsonlRecord::Message(Message {
+                    id: message_id,
+                    session_id,
+                    role: row.get(5).map_err(|e| e.to_string())?,
+                    content: row.get(6).map_err(|e| e.to_string())?,
+                    timestamp: row.get(7).map_err(|e| e.to_string())?,
+                    status: MessageStatus::from_db(&row.get::<_, String>(10).map_err(|e| e.to_string())?),
+                }))?;
+                
+                processed += 1;
+                if processed.is_multiple_of(PROGRESS_INTERVAL as u64) {
+                    progress(TransferProgress { processed, total });
+                }
+            }
+        }
+        
+        writer.flush().map_err(|e| format!("Failed to write file: {}", e))?;
+        progress(TransferProgress { processed, total });
+        
+        Ok(())
+    }
+    
+    // JSON Lines形式のファイルを1行ずつ読み込んでインポートする
+    fn import_jsonl(
+        &self,
+        file_path: &str,
+        strategy: MergeStrategy,
+        dry_run: bool,
+        progress: &mut dyn FnMut(TransferProgress),
+    ) -> std::result::Result<ImportReport, String> {
+        let file = fs::File::open(file_path)
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        let total = file.metadata()
+            .map(|m| m.len())
+            .map_err(|e| format!("Failed to read file: {}", e))?;
+        let mut reader = BufReader::new(file);
+        
+        let mut report = ImportReport {
+            source: ImportSource::Jsonl,
+            file_path: file_path.to_string(),
+            dry_run,
+            strategy,
+            sessions: Vec::new(),
+            message_count: 0,
+            skipped_messages: 0,
+            summary: ImportSummary::default(),
+        };
+        let mut session_ids: HashMap<String, Option<String>> = HashMap::new();
+        
+        // トランザクションを開始
+        let mut conn = self.writer();
+        let tx = conn.transaction()
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        
+        let mut line = String::new();
+        let mut line_number = 0;
+        let mut processed: u64 = 0;
+        let mut header_seen = false;
+        
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)
+                .map_err(|e| format!("Failed to read file: {}", e))?;
+            if read == 0 {
+                break;
+            }
+            line_number += 1;
+            processed += read as u64;
+            
+            if line.trim().is_empty() {
+                continue;
+            }
+            
+            let record: JsonlRecord = serde_json::from_str(&line)
+                .map_err(|e| format!("Failed to parse line {}: {}", line_number, e))?;
+            
+            match record {
+                JsonlRecord::Header { version, .. } => {
+                    importers::validate_version(&version)?;
+                    header_seen = true;
+                }
+                _ if !header_seen => {
+                    return Err("JSON Linesファイルの先頭にヘッダーがありません".to_string());
+                }
+                JsonlRecord::Session(session) => {
+                    importers::validate_session(&session)
+                        .map_err(|e| format!("{}行目: {}", line_number, e))?;
+                    let target_id = Self::merge_session(&tx, &session, strategy, &mut report.summary)?;
+                    report.sessions.push(ImportedSessionSummary {
+                        id: session.id.clone(),
+                        title: session.title,
+                        message_count: 0,
+                    });
+                    session_ids.insert(session.id, target_id);
+                }
+                JsonlRecord::Message(message) => {
+                    importers::validate_message(&message)
+                        .map_err(|e| format!("{}行目: {}", line_number, e))?;
+                    Self::merge_message(&tx, &message, &session_ids, strategy, &mut report.summary)?;
+                    report.message_count += 1;
+                    // メッセージは直前のセッションの後に続いて書き出されている
+                    if let Some(session) = report.sessions.last_mut().filter(|s| s.id == message.session_id) {
+                        session.message_count += 1;
+                    }
+                    
+                    if report.message_count.is_multiple_of(PROGRESS_INTERVAL) {
+                        progress(TransferProgress { processed, total });
+                    }
+                }
+            }
+        }
+        
+        progress(TransferProgress { processed: total, total });
+        
+        // ドライランの場合はコミットせずにロールバックする
+        if dry_run {
+            return Ok(report);
+        }
+        
+        tx.commit().map_err(|e| format!("Failed to commit transaction: {}", e))?;
+        
+        Ok(report)
+    }
+    
+    pub fn create_session(&self, title: &str) -> Result<String> {
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        
+        self.writer().execute(
+            "INSERT INTO chat_sessions (id, title, created_at, updated_at) VALUES (?, ?, ?, ?)",
+            params![id, title, now, now],
+        )?;
+        
+        Ok(id)
+    }
+    
+    // 指定したメッセージまでをコピーした新しいセッションを作成し、そのIDを返す
+    // フォルダ、タグ、添付した資料、応答に添えた引用も引き継ぎ、元のセッションは変更しない
+    pub fn fork_session(&self, session_id: &str, message_id: &str) -> std::result::Result<String, String> {
+        let mut conn = self.writer();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        
+        let seq: i64 = tx.query_row(
+            "SELECT m.seq FROM messages m JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.id = ? AND m.session_id = ? AND m.deleted_at IS NULL AND s.deleted_at IS NULL",
+            params![message_id, session_id],
+            |row| row.get(0),
+        ).map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => "分岐元のメッセージが見つかりません".to_string(),
+            e => e.to_string(),
+        })?;
+        
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        tx.execute(
+            "INSERT INTO chat_sessions (id, title, created_at, updated_at, folder_id, forked_from_session_id, forked_from_message_id)
+             SELECT ?, title || '（分岐）', ?, ?, folder_id, id, ? FROM chat_sessions WHERE id = ?",
+            params![id, now, now, message_id, session_id],
+        ).map_err(|e| e.to_string())?;
+        Self::copy_session_attachments(&tx, session_id, &id).map_err(|e| e.to_string())?;
+        
+        // 生成中のメッセージとゴミ箱のメッセージはコピーしない（連番は元のまま）
+        let messages: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM messages
+                 WHERE session_id = ? AND seq <= ? AND deleted_at IS NULL AND status != 'pending'
+                 ORDER BY seq"
+            ).map_err(|e| e.to_string())?;
+            let ids = stmt.query_map(params![session_id, seq], |row| row.get(0))
+                .map_err(|e| e.to_string())?
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| e.to_string())?;
+            ids
+        };
+        for source_id in &messages {
+            let copy_id = Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO messages (id, session_id, role, content, timestamp, seq, status, model)
+                 SELECT ?, ?, role, content, timestamp, seq, status, model FROM messages WHERE id = ?",
+                params![copy_id, id, source_id],
+            ).map_err(|e| e.to_string())?;
+            tx.execute(
+                "INSERT INTO message_citations (message_id, position, cited_message_id, score)
+                 SELECT ?, position, cited_message_id, score FROM message_citations WHERE message_id = ?",
+                params![copy_id, source_id],
+            ).map_err(|e| e.to_string())?;
+            // メモは分岐先にも引き継ぐ（スターは一覧が重複しないように引き継がない）
+            tx.execute(
+                "INSERT INTO message_notes (message_id, content, updated_at)
+                 SELECT ?, content, updated_at FROM message_notes WHERE message_id = ?",
+                params![copy_id, source_id],
+            ).map_err(|e| e.to_string())?;
+        }
+        
+        tx.commit().map_err(|e| e.to_string())?;
+        
+        Ok(id)
+    }
+    
+    // 指定したメッセージ以降を新しいセッションに移し、そのIDを返す
+    // 移したメッセージの連番は1から振り直し、両方のセッションの更新日時を最後のメッセージに合わせる
+    pub fn split_session(&self, session_id: &str, message_id: &str) -> std::result::Result<String, String> {
+        let mut conn = self.writer();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        Self::ensure_no_pending(&tx, session_id)?;
+        
+        let (seq, timestamp): (i64, String) = tx.query_row(
+            "SELECT m.seq, m.timestamp FROM messages m JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.id = ? AND m.session_id = ? AND m.deleted_at IS NULL AND s.deleted_at IS NULL",
+            params![message_id, session_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => "分割するメッセージが見つかりません".to_string(),
+            e => e.to_string(),
+        })?;
+        
+        let has_earlier: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM messages WHERE session_id = ? AND seq < ? AND deleted_at IS NULL)",
+            params![session_id, seq],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        if !has_earlier {
+            return Err("最初のメッセージでは分割できません".to_string());
+        }
+        
+        let id = Uuid::new_v4().to_string();
+        tx.execute(
+            "INSERT INTO chat_sessions (id, title, created_at, updated_at, folder_id)
+             SELECT ?, title || '（続き）', ?, ?, folder_id FROM chat_sessions WHERE id = ?",
+            params![id, timestamp, timestamp, session_id],
+        ).map_err(|e| e.to_string())?;
+        Self::copy_session_attachments(&tx, session_id, &id).map_err(|e| e.to_string())?;
+        
+        // ゴミ箱にあるメッセージも元の位置に従って移す
+        tx.execute(
+            "UPDATE messages SET session_id = ?, seq = seq - ? WHERE session_id = ? AND seq >= ?",
+            params![id, seq - 1, session_id, seq],
+        ).map_err(|e| e.to_string())?;
+        
+        Self::refresh_session_updated_at(&tx, session_id).map_err(|e| e.to_string())?;
+        Self::refresh_session_updated_at(&tx, &id).map_err(|e| e.to_string())?;
+        
+        tx.commit().map_err(|e| e.to_string())?;
+        
+        Ok(id)
+    }
+    
+    // 他のセッションのメッセージをtarget_idのセッションに移し、移し終えたセッションを削除する
+    // タグと添付した資料は統合先に引き継ぎ、作成日時は最も古いもの、更新日時は最後のメッセージに合わせる
+    pub fn merge_sessions(
+        &self,
+        target_id: &str,
+        source_ids: &[String],
+        mode: SessionMergeMode,
+    ) -> std::result::Result<(), String> {
+        if source_ids.is_empty() {
+            return Err("統合するセッションを選択してください".to_string());
+        }
+        if source_ids.iter().any(|source_id| source_id == target_id) {
+            return Err("統合先のセッションを統合元に含めることはできません".to_string());
+        }
+        
+        let mut conn = self.writer();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        
+        for session_id in std::iter::once(target_id).chain(source_ids.iter().map(String::as_str)) {
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM chat_sessions WHERE id = ? AND deleted_at IS NULL)",
+                params![session_id],
+                |row| row.get(0),
+            ).map_err(|e| e.to_string())?;
+            if !exists {
+                return Err("統合するセッションが見つかりません".to_string());
+            }
+            Self::ensure_no_pending(&tx, session_id)?;
+        }
+        
+        for source_id in source_ids {
+            // 統合先の末尾に続けて連番を振る
+            tx.execute(
+                "UPDATE messages SET session_id = ?1, seq = seq + (SELECT COALESCE(MAX(seq), 0) FROM messages WHERE session_id = ?1)
+                 WHERE session_id = ?2",
+                params![target_id, source_id],
+            ).map_err(|e| e.to_string())?;
+            Self::copy_session_attachments(&tx, source_id, target_id).map_err(|e| e.to_string())?;
+            tx.execute(
+                "UPDATE chat_sessions SET created_at = MIN(created_at, (SELECT created_at FROM chat_sessions WHERE id = ?2))
+                 WHERE id = ?1",
+                params![target_id, source_id],
+            ).map_err(|e| e.to_string())?;
+            
+            // 統合元から分岐したセッションは統合先から分岐したものとする
+            tx.execute(
+                "UPDATE chat_sessions SET forked_from_session_id = ?1 WHERE forked_from_session_id = ?2",
+                params![target_id, source_id],
+            ).map_err(|e| e.to_string())?;
+            tx.execute("DELETE FROM chat_sessions WHERE id = ?", params![source_id])
+                .map_err(|e| e.to_string())?;
+        }
+        tx.execute(
+            "UPDATE chat_sessions SET forked_from_session_id = NULL, forked_from_message_id = NULL
+             WHERE id = ?1 AND forked_from_session_id = ?1",
+            params![target_id],
+        ).map_err(|e| e.to_string())?;
+        
+        // 時系列で並べる場合はタイムスタンプ順に振り直す（同時刻は統合前の順番）
+        if mode == SessionMergeMode::Interleave {
+            tx.execute(
+                "UPDATE messages SET seq = numbered.seq
+                 FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY timestamp, seq) AS seq FROM messages WHERE session_id = ?) AS numbered
+                 WHERE messages.id = numbered.id",
+                params![target_id],
+            ).map_err(|e| e.to_string())?;
+        }
+        
+        Self::refresh_session_updated_at(&tx, target_id).map_err(|e| e.to_string())?;
+        
+        tx.commit().map_err(|e| e.to_string())
+    }
+    
+    // 応答の生成中にメッセージを動かすと、応答が別のセッションに書き込まれてしまう
+    fn ensure_no_pending(conn: &Connection, session_id: &str) -> std::result::Result<(), String> {
+        let pending: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM messages WHERE session_id = ? AND status = 'pending')",
+            params![session_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())?;
+        
+        if pending {
+            return Err("応答の生成中のセッションは分割・統合できません".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    // タグと添付した資料を別のセッションにも付ける
+    fn copy_session_attachments(conn: &Connection, from_session_id: &str, to_session_id: &str) -> Result<()> {
+        conn.execute(
+            "INSERT OR IGNORE INTO session_tags (session_id, tag_id) SELECT ?, tag_id FROM session_tags WHERE session_id = ?",
+            params![to_session_id, from_session_id],
+        )?;
+        conn.execute(
+            "INSERT OR IGNORE INTO session_collections (session_id, collection_id)
+             SELECT ?, collection_id FROM session_collections WHERE session_id = ?",
+            params![to_session_id, from_session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // 更新日時を最後のメッセージの日時にする（メッセージがなければ作成日時）
+    fn refresh_session_updated_at(conn: &Connection, session_id: &str) -> Result<()> {
+        conn.execute(
+            "UPDATE chat_sessions
+             SET updated_at = COALESCE((SELECT MAX(timestamp) FROM messages WHERE session_id = ?1 AND deleted_at IS NULL), created_at)
+             WHERE id = ?1",
+            params![session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn add_message(&self, session_id: &str, role: &str, content: &str) -> Result<String> {
+        let message = Self::insert_message(&self.writer(), session_id, role, content, MessageStatus::Complete)?;
+        
+        Ok(message.id)
+    }
+    
+    // メッセージを末尾に追加し、セッションの更新日時を更新する
+    fn insert_message(
+        conn: &Connection,
+        session_id: &str,
+        role: &str,
+        content: &str,
+        status: MessageStatus,
+    ) -> Result<Message> {
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        
+        // 連番は挿入と同じ文で採番するため、同時に書き込まれても重複しない
+        conn.execute(
+            &format!(
+                "INSERT INTO messages (id, session_id, role, content, timestamp, status, seq) VALUES (?, ?, ?, ?, ?, ?, {})",
+                NEXT_MESSAGE_SEQ
+            ),
+            params![id, session_id, role, content, now, status.as_str(), session_id],
+        )?;
+        
+        // セッションの更新日時を更新
+        conn.execute(
+            "UPDATE chat_sessions SET updated_at = ? WHERE id = ?",
+            params![now, session_id],
+        )?;
+        
+        Ok(Message {
+            id,
+            session_id: session_id.to_string(),
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: now,
+            status,
+        })
+    }
+    
+    // ユーザーメッセージと応答用のプレースホルダーを1つのトランザクションで保存する
+    pub fn begin_turn(&self, session_id: &str, content: &str) -> Result<PendingTurn> {
+        // 生成中や失敗したメッセージはモデルに送らない
+        let mut history: Vec<Message> = self.get_messages(session_id)?
+            .into_iter()
+            .filter(|message| message.status == MessageStatus::Complete)
+            .collect();
+        
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let user_message = Self::insert_message(&tx, session_id, "user", content, MessageStatus::Complete)?;
+        let placeholder = Self::insert_message(&tx, session_id, "assistant", "", MessageStatus::Pending)?;
+        tx.commit()?;
+        
+        history.push(user_message.clone());
+        
+        Ok(PendingTurn {
+            user_message,
+            assistant_message_id: placeholder.id,
+            history,
+        })
+    }
+    
+    // プレースホルダーに応答（またはエラー）を書き込み、確定したメッセージを返す
+    pub fn finish_turn(&self, message_id: &str, content: &str, status: MessageStatus, model: Option<&str>) -> Result<Message> {
+        let now = Utc::now().to_rfc3339();
+        let conn = self.writer();
+        
+        conn.execute(
+            "UPDATE messages SET content = ?, status = ?, timestamp = ?, model = ? WHERE id = ?",
+            params![content, status.as_str(), now, model, message_id],
+        )?;
+        conn.execute(
+            "UPDATE chat_sessions SET updated_at = ? WHERE id = (SELECT session_id FROM messages WHERE id = ?)",
+            params![now, message_id],
+        )?;
+        
+        conn.query_row(
+            "SELECT id, session_id, role, content, timestamp, status FROM messages WHERE id = ?",
+            params![message_id],
+            Self::message_from_row,
+        )
+    }
+    
+    // 前回の終了時に生成中のまま残ったプレースホルダーをエラーにする
+    pub fn recover_pending_messages(&self) -> Result<usize> {
+        self.writer().execute(
+            "UPDATE messages SET status = 'error', content = '応答の生成中にアプリケーションが終了しました'
+             WHERE status = 'pending'",
+            [],
+        )
+    }
+    
+    // 指定したモデルの埋め込みがまだないメッセージを新しい順に返す（ID、内容、ゴミ箱にあるセッションのメッセージは除く）
+    pub fn pending_embeddings(&self, model: &str, limit: usize) -> Result<Vec<(String, String)>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.content FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             LEFT JOIN message_embeddings e ON e.message_id = m.id AND e.model = ?1
+             LEFT JOIN embedding_failures f ON f.message_id = m.id AND f.model = ?1
+             WHERE e.message_id IS NULL AND f.message_id IS NULL AND m.status = 'complete'
+               AND m.deleted_at IS NULL AND s.deleted_at IS NULL AND m.content != ''
+             ORDER BY m.timestamp DESC
+             LIMIT ?2"
+        )?;
+        
+        let pending = stmt.query_map(params![model, limit as i64], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        
+        Ok(pending)
+    }
+    
+    // 計算した埋め込みを保存する（計算中に完全に削除されたメッセージは無視する）
+    pub fn save_embeddings(&self, model: &str, embeddings: &[(String, Vec<f32>)]) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        for (message_id, vector) in embeddings {
+            tx.execute(
+                "INSERT OR REPLACE INTO message_embeddings (message_id, model, vector)
+                 SELECT ?1, ?2, ?3 WHERE EXISTS (SELECT 1 FROM messages WHERE id = ?1)",
+                params![message_id, model, embeddings::to_blob(vector)],
+            )?;
+        }
+        
+        tx.commit()
+    }
+    
+    // 埋め込みAPIに拒否されたメッセージを記録し、以降の計算対象から外す
+    pub fn record_embedding_failures(&self, model: &str, failures: &[(String, String)]) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        let now = Utc::now().to_rfc3339();
+        
+        for (message_id, error) in failures {
+            tx.execute(
+                "INSERT OR REPLACE INTO embedding_failures (message_id, model, error, failed_at)
+                 SELECT ?1, ?2, ?3, ?4 WHERE EXISTS (SELECT 1 FROM messages WHERE id = ?1)",
+                params![message_id, model, error, now],
+            )?;
+        }
+        
+        tx.commit()
+    }
+    
+    pub fn embedding_status(&self, model: &str) -> Result<EmbeddingStatus> {
+        let conn = self.reader();
+        
+        let total = conn.query_row(
+            "SELECT COUNT(*) FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.status = 'complete' AND m.deleted_at IS NULL AND s.deleted_at IS NULL AND m.content != ''",
+            [],
+            |row| row.get(0),
+        )?;
+        let indexed = conn.query_row(
+            "SELECT COUNT(*) FROM message_embeddings e
+             JOIN messages m ON m.id = e.message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE e.model = ? AND m.deleted_at IS NULL AND s.deleted_at IS NULL",
+            params![model],
+            |row| row.get(0),
+        )?;
+        let failed = conn.query_row(
+            "SELECT COUNT(*) FROM embedding_failures f
+             JOIN messages m ON m.id = f.message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE f.model = ? AND m.deleted_at IS NULL AND s.deleted_at IS NULL",
+            params![model],
+            |row| row.get(0),
+        )?;
+        
+        Ok(EmbeddingStatus { model: model.to_string(), indexed, failed, total })
+    }
+    
+    // 正規化したクエリのベクトルに近いメッセージを、類似度の高い順にlimit件返す
+    // 個人の履歴の規模であれば、すべてのベクトルとの内積を計算しても十分に速い
+    // exclude_session_idを指定した場合、そのセッションのメッセージは除く
+    pub fn semantic_search(
+        &self,
+        model: &str,
+        query: &[f32],
+        limit: usize,
+        exclude_session_id: Option<&str>,
+    ) -> Result<Vec<SemanticSearchResult>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.session_id, m.role, m.content, m.timestamp, m.status, s.title, e.vector
+             FROM message_embeddings e
+             JOIN messages m ON m.id = e.message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE e.model = ?1 AND m.deleted_at IS NULL AND s.deleted_at IS NULL
+               AND (?2 IS NULL OR m.session_id != ?2)"
+        )?;
+        let mut rows = stmt.query(params![model, exclude_session_id])?;
+        
+        let mut results: Vec<SemanticSearchResult> = Vec::with_capacity(limit + 1);
+        while let Some(row) = rows.next()? {
+            let vector: Vec<u8> = row.get(7)?;
+            let score = embeddings::dot(query, &embeddings::from_blob(&vector));
+            
+            // 上位limit件に入らないものはメッセージを読み出さない
+            if results.len() >= limit && results.last().is_some_and(|last| last.score >= score) {
+                continue;
+            }
+            
+            results.push(SemanticSearchResult {
+                message: Self::message_from_row(row)?,
+                session_title: row.get(6)?,
+                score,
+            });
+            results.sort_by(|a, b| b.score.total_cmp(&a.score));
+            results.truncate(limit);
+        }
+        
+        Ok(results)
+    }
+    
+    // 応答に添えた過去のメッセージを記録する
+    pub fn save_citations(&self, message_id: &str, citations: &[Citation]) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        for citation in citations {
+            tx.execute(
+                "INSERT OR REPLACE INTO message_citations (message_id, position, cited_message_id, score)
+                 VALUES (?, ?, ?, ?)",
+                params![message_id, citation.position as i64, citation.message_id, citation.score],
+            )?;
+        }
+        
+        tx.commit()
+    }
+    
+    // セッション内の応答ごとに、参考にした過去のメッセージを返す（ゴミ箱にあるメッセージは除く）
+    pub fn get_session_citations(&self, session_id: &str) -> Result<HashMap<String, Vec<Citation>>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT c.message_id, c.position, m.id, m.session_id, s.title, m.role, m.content, m.timestamp, c.score
+             FROM message_citations c
+             JOIN messages citing ON citing.id = c.message_id
+             JOIN messages m ON m.id = c.cited_message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE citing.session_id = ? AND m.deleted_at IS NULL AND s.deleted_at IS NULL
+             ORDER BY c.message_id, c.position"
+        )?;
+        
+        let mut citations: HashMap<String, Vec<Citation>> = HashMap::new();
+        let mut rows = stmt.query(params![session_id])?;
+        while let Some(row) = rows.next()? {
+            let content: String = row.get(6)?;
+            citations.entry(row.get(0)?).or_default().push(Citation {
+                position: row.get::<_, i64>(1)? as usize,
+                message_id: row.get(2)?,
+                session_id: row.get(3)?,
+                session_title: row.get(4)?,
+                role: row.get(5)?,
+                excerpt: recall::excerpt(&content),
+                timestamp: row.get(7)?,
+                score: row.get(8)?,
+            });
+        }
+        
+        Ok(citations)
+    }
+    
+    pub fn set_message_bookmarked(&self, message_id: &str, bookmarked: bool) -> Result<()> {
+        let conn = self.writer();
+        if bookmarked {
+            conn.execute(
+                "INSERT OR IGNORE INTO message_bookmarks (message_id, created_at) VALUES (?, ?)",
+                params![message_id, Utc::now().to_rfc3339()],
+            )?;
+        } else {
+            conn.execute("DELETE FROM message_bookmarks WHERE message_id = ?", params![message_id])?;
+        }
+        Ok(())
+    }
+    
+    // メッセージのメモを保存する（空のメモは削除する）
+    pub fn set_message_note(&self, message_id: &str, note: Option<&str>) -> Result<()> {
+        let conn = self.writer();
+        match note.map(str::trim).filter(|note| !note.is_empty()) {
+            Some(note) => {
+                conn.execute(
+                    "INSERT INTO message_notes (message_id, content, updated_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(message_id) DO UPDATE SET content = ?2, updated_at = ?3",
+                    params![message_id, note, Utc::now().to_rfc3339()],
+                )?;
+            }
+            None => {
+                conn.execute("DELETE FROM message_notes WHERE message_id = ?", params![message_id])?;
+            }
+        }
+        Ok(())
+    }
+    
+    // セッション内のメッセージごとのスターとメモ（どちらも無いメッセージとゴミ箱にあるメッセージは含まない）
+    pub fn get_session_annotations(&self, session_id: &str) -> Result<HashMap<String, MessageAnnotation>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, b.message_id IS NOT NULL, n.content
+             FROM messages m
+             LEFT JOIN message_bookmarks b ON b.message_id = m.id
+             LEFT JOIN message_notes n ON n.message_id = m.id
+             WHERE m.session_id = ? AND m.deleted_at IS NULL AND (b.message_id IS NOT NULL OR n.message_id IS NOT NULL)"
+        )?;
+        
+        let annotations = stmt.query_map(params![session_id], |row| {
+            Ok((row.get(0)?, MessageAnnotation { starred: row.get(1)?, note: row.get(2)? }))
+        })?
+        .collect::<Result<HashMap<_, _>>>()?;
+        
+        Ok(annotations)
+    }
+    
+    // スターを付けたメッセージを、付けた日時の新しい順にすべてのセッションから返す（ゴミ箱のものは除く）
+    pub fn get_bookmarks(&self) -> Result<Vec<Bookmark>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.session_id, m.role, m.content, m.timestamp, m.status, s.title, n.content, b.created_at
+             FROM message_bookmarks b
+             JOIN messages m ON m.id = b.message_id
+             JOIN chat_sessions s ON s.id = m.session_id
+             LEFT JOIN message_notes n ON n.message_id = m.id
+             WHERE m.deleted_at IS NULL AND s.deleted_at IS NULL
+             ORDER BY b.created_at DESC"
+        )?;
+        
+        let bookmarks = stmt.query_map([], |row| {
+            Ok(Bookmark {
+                message: Self::message_from_row(row)?,
+                session_title: row.get(6)?,
+                note: row.get(7)?,
+                bookmarked_at: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+        
+        Ok(bookmarks)
+    }
+    
+    // メッセージIDごとのメモ（エクスポート用）
+    fn get_session_notes(&self, session_id: &str) -> Result<HashMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT n.message_id, n.content
+             FROM message_notes n
+             JOIN messages m ON m.id = n.message_id
+             WHERE m.session_id = ? AND m.deleted_at IS NULL"
+        )?;
+        
+        let notes = stmt.query_map(params![session_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>>>()?;
+        
+        Ok(notes)
+    }
+    
+    // メッセージIDごとの応答したモデル（エクスポート用）
+    fn get_session_models(&self, session_id: &str) -> Result<HashMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, model FROM messages WHERE session_id = ? AND model IS NOT NULL"
+        )?;
+        
+        let models = stmt.query_map(params![session_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<HashMap<_, _>>>()?;
+        
+        Ok(models)
+    }
+    
+    pub fn create_collection(&self, name: &str, folder: &str) -> Result<String> {
+        let id = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+        
+        self.writer().execute(
+            "INSERT INTO knowledge_collections (id, name, folder, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+            params![id, name, folder, now, now],
+        )?;
+        
+        Ok(id)
+    }
+    
+    // セッションへの添付も削除される（取り込んだ資料の保存先はknowledge_store::deleteで削除する）
+    pub fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        self.writer().execute("DELETE FROM knowledge_collections WHERE id = ?", params![collection_id])?;
+        
+        Ok(())
+    }
+    
+    // 資料とチャンクの数はコレクションの保存先から数えるので、ここでは0を返す
+    pub fn get_collections(&self) -> Result<Vec<KnowledgeCollection>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, folder, created_at, updated_at
+             FROM knowledge_collections
+             ORDER BY name"
+        )?;
+        
+        let collections = stmt.query_map([], |row| {
+            Ok(KnowledgeCollection {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                folder: row.get(2)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
+                document_count: 0,
+                chunk_count: 0,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+        
+        Ok(collections)
+    }
+    
+    pub fn get_collection_folder(&self, collection_id: &str) -> Result<String> {
+        self.reader().query_row(
+            "SELECT folder FROM knowledge_collections WHERE id = ?",
+            params![collection_id],
+            |row| row.get(0),
+        )
+    }
+    
+    // 資料を取り込んだ日時を記録する
+    pub fn touch_collection(&self, collection_id: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE knowledge_collections SET updated_at = ? WHERE id = ?",
+            params![Utc::now().to_rfc3339(), collection_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // セッションに添付したコレクションのID
+    pub fn get_session_collections(&self, session_id: &str) -> Result<Vec<String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare("SELECT collection_id FROM session_collections WHERE session_id = ?")?;
+        
+        let ids = stmt.query_map(params![session_id], |row| row.get(0))?
+            .collect::<Result<Vec<_>>>()?;
+        
+        Ok(ids)
+    }
+    
+    pub fn set_session_collections(&self, session_id: &str, collection_ids: &[String]) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        tx.execute("DELETE FROM session_collections WHERE session_id = ?", params![session_id])?;
+        for collection_id in collection_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO session_collections (session_id, collection_id) VALUES (?, ?)",
+                params![session_id, collection_id],
+            )?;
+        }
+        
+        tx.commit()
+    }
+    
+    // ゴミ箱以外のすべてのセッションを返す（アーカイブ済みも含む）
+    pub fn get_sessions(&self) -> Result<Vec<ChatSession>> {
+        self.query_sessions(&SessionQuery::default())
+    }
+    
+    fn session_from_row(row: &rusqlite::Row) -> Result<ChatSession> {
+        let tags: Option<String> = row.get(7)?;
+        
+        Ok(ChatSession {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            pinned: row.get(4)?,
+            archived: row.get(5)?,
+            folder_id: row.get(6)?,
+            tags: tags.and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default(),
+            forked_from_session_id: row.get(8)?,
+            forked_from_message_id: row.get(9)?,
+        })
+    }
+    
+    // 絞り込み条件のWHERE句とパラメータを組み立てる
+    fn session_query_filter(query: &SessionQuery) -> (String, Vec<rusqlite::types::Value>) {
+        use rusqlite::types::Value;
+        
+        let mut conditions = vec!["s.deleted_at IS NULL".to_string()];
+        let mut values: Vec<Value> = Vec::new();
+        
+        match query.folder_id.as_deref() {
+            Some("") => conditions.push("s.folder_id IS NULL".to_string()),
+            Some(folder_id) if query.include_subfolders => {
+                conditions.push(
+                    "s.folder_id IN (
+                         WITH RECURSIVE subtree(id) AS (
+                             SELECT ?
+                             UNION ALL
+                             SELECT f.id FROM folders f JOIN subtree ON f.parent_id = subtree.id
+                         )
+                         SELECT id FROM subtree
+                     )".to_string(),
+                );
+                values.push(Value::Text(folder_id.to_string()));
+            }
+            Some(folder_id) => {
+                conditions.push("s.folder_id = ?".to_string());
+                values.push(Value::Text(folder_id.to_string()));
+            }
+            None => {}
+        }
+        
+        if let Some(tag_id) = &query.tag_id {
+            conditions.push("EXISTS (SELECT 1 FROM session_tags st WHERE st.session_id = s.id AND st.tag_id = ?)".to_string());
+            values.push(Value::Text(tag_id.clone()));
+        }
+        
+        if let Some(archived) = query.archived {
+            conditions.push("s.archived = ?".to_string());
+            values.push(Value::Integer(archived as i64));
+        }
+        
+        (conditions.join(" AND "), values)
+    }
+    
+    // 条件に合うセッションを、ピン留めを先頭に更新日時の新しい順で返す
+    pub fn query_sessions(&self, query: &SessionQuery) -> Result<Vec<ChatSession>> {
+        let (filter, mut values) = Self::session_query_filter(query);
+        
+        // LIMIT -1は上限なし
+        values.push(rusqlite::types::Value::Integer(query.limit.map_or(-1, |limit| limit as i64)));
+        values.push(rusqlite::types::Value::Integer(query.offset as i64));
+        
+        let conn = self.reader();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM chat_sessions s
+             WHERE {}
+             ORDER BY s.pinned DESC, s.updated_at DESC
+             LIMIT ? OFFSET ?",
+            SESSION_COLUMNS, filter
+        ))?;
+        
+        let sessions = stmt.query_map(rusqlite::params_from_iter(values), Self::session_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+        
+        Ok(sessions)
+    }
+    
+    pub fn count_sessions(&self, query: &SessionQuery) -> Result<usize> {
+        let (filter, values) = Self::session_query_filter(query);
+        
+        self.reader().query_row(
+            &format!("SELECT COUNT(*) FROM chat_sessions s WHERE {}", filter),
+            rusqlite::params_from_iter(values),
+            |row| row.get(0),
+        )
+    }
+    
+    // ユーザーがセッションの名前を変更する
+    pub fn rename_session(&self, session_id: &str, title: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE chat_sessions SET title = ?, title_edited = 1 WHERE id = ?",
+            params![title, session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // 自動生成したタイトルを設定する（ユーザーが名前を変更済みの場合は何もせずfalseを返す）
+    pub fn set_generated_title(&self, session_id: &str, title: &str) -> Result<bool> {
+        let updated = self.writer().execute(
+            "UPDATE chat_sessions SET title = ? WHERE id = ? AND title_edited = 0",
+            params![title, session_id],
+        )?;
+        
+        Ok(updated > 0)
+    }
+    
+    pub fn set_session_pinned(&self, session_id: &str, pinned: bool) -> Result<()> {
+        self.writer().execute(
+            "UPDATE chat_sessions SET pinned = ? WHERE id = ?",
+            params![pinned, session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn set_session_archived(&self, session_id: &str, archived: bool) -> Result<()> {
+        self.writer().execute(
+            "UPDATE chat_sessions SET archived = ? WHERE id = ?",
+            params![archived, session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // セッションをフォルダに移動する（Noneの場合はフォルダから出す）
+    pub fn move_session_to_folder(&self, session_id: &str, folder_id: Option<&str>) -> Result<()> {
+        self.writer().execute(
+            "UPDATE chat_sessions SET folder_id = ? WHERE id = ?",
+            params![folder_id, session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn get_folders(&self) -> Result<Vec<Folder>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, parent_id, created_at FROM folders ORDER BY name COLLATE NOCASE"
+        )?;
+        
+        let folders = stmt.query_map([], |row| {
+            Ok(Folder {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                parent_id: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+        
+        Ok(folders)
+    }
+    
+    pub fn create_folder(&self, name: &str, parent_id: Option<&str>) -> Result<String> {
+        let id = Uuid::new_v4().to_string();
+        
+        self.writer().execute(
+            "INSERT INTO folders (id, name, parent_id, created_at) VALUES (?, ?, ?, ?)",
+            params![id, name, parent_id, Utc::now().to_rfc3339()],
+        )?;
+        
+        Ok(id)
+    }
+    
+    pub fn rename_folder(&self, folder_id: &str, name: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE folders SET name = ? WHERE id = ?",
+            params![name, folder_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // フォルダを別のフォルダの下に移動する（自分自身や子孫の下には移動できない）
+    pub fn move_folder(&self, folder_id: &str, parent_id: Option<&str>) -> std::result::Result<(), String> {
+        let conn = self.writer();
+        
+        if let Some(parent_id) = parent_id {
+            let is_descendant: bool = conn.query_row(
+                "WITH RECURSIVE subtree(id) AS (
+                     SELECT ?
+                     UNION ALL
+                     SELECT f.id FROM folders f JOIN subtree ON f.parent_id = subtree.id
+                 )
+                 SELECT EXISTS(SELECT 1 FROM subtree WHERE id = ?)",
+                params![folder_id, parent_id],
+                |row| row.get(0),
+            ).map_err(|e| e.to_string())?;
+            
+            if is_descendant {
+                return Err("フォルダを自分自身またはサブフォルダの中に移動することはできません".to_string());
+            }
+        }
+        
+        conn.execute(
+            "UPDATE folders SET parent_id = ? WHERE id = ?",
+            params![parent_id, folder_id],
+        ).map_err(|e| e.to_string())?;
+        
+        Ok(())
+    }
+    
+    // フォルダを削除する。中のセッションとサブフォルダは親フォルダに移動する
+    pub fn delete_folder(&self, folder_id: &str) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        let parent_id: Option<String> = tx.query_row(
+            "SELECT parent_id FROM folders WHERE id = ?",
+            params![folder_id],
+            |row| row.get(0),
+        )?;
+        
+        tx.execute(
+            "UPDATE chat_sessions SET folder_id = ? WHERE folder_id = ?",
+            params![parent_id, folder_id],
+        )?;
+        tx.execute(
+            "UPDATE folders SET parent_id = ? WHERE parent_id = ?",
+            params![parent_id, folder_id],
+        )?;
+        tx.execute(
+            "DELETE FROM folders WHERE id = ?",
+            params![folder_id],
+        )?;
+        
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    pub fn get_tags(&self) -> Result<Vec<Tag>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.name,
+                    (SELECT COUNT(*) FROM session_tags st
+                     JOIN chat_sessions s ON s.id = st.session_id
+                     WHERE st.tag_id = t.id AND s.deleted_at IS NULL)
+             FROM tags t ORDER BY t.name COLLATE NOCASE"
+        )?;
+        
+        let tags = stmt.query_map([], |row| {
+            Ok(Tag {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                session_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+        
+        Ok(tags)
+    }
+    
+    // タグ付けは外部キーのON DELETE CASCADEで削除される
+    pub fn delete_tag(&self, tag_id: &str) -> Result<()> {
+        self.writer().execute("DELETE FROM tags WHERE id = ?", params![tag_id])?;
+        
+        Ok(())
+    }
+    
+    // セッションのタグを指定した名前の一覧に置き換える（存在しないタグは作成する）
+    pub fn set_session_tags(&self, session_id: &str, tags: &[String]) -> Result<()> {
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        tx.execute("DELETE FROM session_tags WHERE session_id = ?", params![session_id])?;
+        
+        for name in tags.iter().map(|name| name.trim()).filter(|name| !name.is_empty()) {
+            tx.execute(
+                "INSERT OR IGNORE INTO tags (id, name) VALUES (?, ?)",
+                params![Uuid::new_v4().to_string(), name],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO session_tags (session_id, tag_id)
+                 SELECT ?, id FROM tags WHERE name = ?",
+                params![session_id, name],
+            )?;
+        }
+        
+        tx.commit()?;
+        
+        Ok(())
+    }
+    
+    pub fn get_messages(&self, session_id: &str) -> Result<Vec<Message>> {
+        self.get_messages_page(session_id, &MessagePage::default())
+    }
+    
+    // カーソル（メッセージID）を基準にメッセージを読み込む
+    // 結果は常に古い順に並ぶ
+    pub fn get_messages_page(&self, session_id: &str, page: &MessagePage) -> Result<Vec<Message>> {
+        let mut sql = String::from(
+            "SELECT id, session_id, role, content, timestamp, status FROM messages
+             WHERE session_id = ? AND deleted_at IS NULL"
+        );
+        let mut values: Vec<String> = vec![session_id.to_string()];
+        
+        // 順序はタイムスタンプではなくセッション内の連番で決める
+        if let Some(before) = &page.before {
+            sql.push_str(" AND seq < (SELECT seq FROM messages WHERE id = ?)");
+            values.push(before.clone());
+        }
+        if let Some(after) = &page.after {
+            sql.push_str(" AND seq > (SELECT seq FROM messages WHERE id = ?)");
+            values.push(after.clone());
+        }
+        
+        // afterのみ指定された場合はカーソルの直後から、それ以外は新しい方から読み込む
+        let newest_first = page.limit.is_some() && page.after.is_none();
+        if newest_first {
+            sql.push_str(" ORDER BY seq DESC");
+        } else {
+            sql.push_str(" ORDER BY seq ASC");
+        }
+        if let Some(limit) = page.limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+        
+        let conn = self.reader();
+        let mut stmt = conn.prepare(&sql)?;
+        let mut messages = stmt.query_map(rusqlite::params_from_iter(values), Self::message_from_row)?
+            .collect::<Result<Vec<_>>>()?;
+        
+        if newest_first {
+            messages.reverse();
+        }
+        
+        Ok(messages)
+    }
+    
+    // id, session_id, role, content, timestamp, statusの順に読み出した行をMessageにする
+    fn message_from_row(row: &rusqlite::Row) -> Result<Message> {
+        Ok(Message {
+            id: row.get(0)?,
+            session_id: row.get(1)?,
+            role: row.get(2)?,
+            content: row.get(3)?,
+            timestamp: row.get(4)?,
+            status: MessageStatus::from_db(&row.get::<_, String>(5)?),
+        })
+    }
+    
+    // セッションのメッセージ数（ゴミ箱のものは除く）
+    pub fn count_messages(&self, session_id: &str) -> Result<usize> {
+        self.reader().query_row(
+            "SELECT COUNT(*) FROM messages WHERE session_id = ? AND deleted_at IS NULL",
+            params![session_id],
+            |row| row.get(0),
+        )
+    }
+    
+    // セッションをゴミ箱に移動する
+    pub fn delete_session(&self, session_id: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE chat_sessions SET deleted_at = ?, trashed_by_retention = 0 WHERE id = ? AND deleted_at IS NULL",
+            params![Utc::now().to_rfc3339(), session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // メッセージをゴミ箱に移動する
+    pub fn delete_message(&self, message_id: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE messages SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL",
+            params![Utc::now().to_rfc3339(), message_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn get_trash(&self) -> Result<Trash> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT s.id, s.title, s.created_at, s.updated_at, s.deleted_at,
+                    (SELECT COUNT(*) FROM messages m WHERE m.session_id = s.id AND m.deleted_at IS NULL)
+             FROM chat_sessions s
+             WHERE s.deleted_at IS NOT NULL
+             ORDER BY s.deleted_at DESC"
+        )?;
+        
+        let sessions = stmt.query_map([], |row| {
+            Ok(TrashedSession {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                created_at: row.get(2)?,
+                updated_at: row.get(3)?,
+                deleted_at: row.get(4)?,
+                message_count: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+        
+        // ゴミ箱内のセッションに含まれるメッセージはセッションごと表示されるので除外する
+        let mut stmt = conn.prepare(
+            "SELECT m.id, m.session_id, s.title, m.role, m.content, m.timestamp, m.deleted_at
+             FROM messages m
+             JOIN chat_sessions s ON s.id = m.session_id
+             WHERE m.deleted_at IS NOT NULL AND s.deleted_at IS NULL
+             ORDER BY m.deleted_at DESC"
+        )?;
+        
+        let messages = stmt.query_map([], |row| {
+            Ok(TrashedMessage {
+                id: row.get(0)?,
+                session_id: row.get(1)?,
+                session_title: row.get(2)?,
+                role: row.get(3)?,
+                content: row.get(4)?,
+                timestamp: row.get(5)?,
+                deleted_at: row.get(6)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+        
+        Ok(Trash { sessions, messages })
+    }
+    
+    pub fn restore_session(&self, session_id: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE chat_sessions SET deleted_at = NULL WHERE id = ?",
+            params![session_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    pub fn restore_message(&self, message_id: &str) -> Result<()> {
+        self.writer().execute(
+            "UPDATE messages SET deleted_at = NULL WHERE id = ?",
+            params![message_id],
+        )?;
+        
+        Ok(())
+    }
+    
+    // ゴミ箱内のセッションとそのメッセージを完全に削除する
+    // メッセージとタグ付けは外部キーのON DELETE CASCADEで削除される
+    pub fn purge_session(&self, session_id: &str) -> std::result::Result<(), String> {
+        let deleted = self.writer().execute(
+            "DELETE FROM chat_sessions WHERE id = ? AND deleted_at IS NOT NULL",
+            params![session_id],
+        ).map_err(|e| e.to_string())?;
+        if deleted == 0 {
+            return Err("ゴミ箱にセッションが見つかりません".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    // ゴミ箱内のメッセージを完全に削除する
+    pub fn purge_message(&self, message_id: &str) -> std::result::Result<(), String> {
+        let deleted = self.writer().execute(
+            "DELETE FROM messages WHERE id = ? AND deleted_at IS NOT NULL",
+            params![message_id],
+        ).map_err(|e| e.to_string())?;
+        if deleted == 0 {
+            return Err("ゴミ箱にメッセージが見つかりません".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    // ゴミ箱内の項目を完全に削除し、削除したセッション数とメッセージ数を返す
+    // older_than_daysを指定した場合は、その日数より前にゴミ箱に移動したものだけを対象にする
+    pub fn purge_trash(&self, older_than_days: Option<u32>) -> Result<(usize, usize)> {
+        // 期限が指定されていない場合は、すべての項目が対象になるよう未来の日時を使う
+        // 期限を指定した自動削除では、保持ポリシーでゴミ箱に移動したセッションは削除しない
+        let (cutoff, include_retention) = match older_than_days {
+            Some(days) => ((Utc::now() - chrono::Duration::days(days as i64)).to_rfc3339(), false),
+            None => ("9999-12-31T23:59:59+00:00".to_string(), true),
+        };
+        
+        let mut conn = self.writer();
+        let tx = conn.transaction()?;
+        
+        // カスケードで削除された行は件数に含まれないため、メッセージは先に削除して数える
+        let mut purged_messages = tx.execute(
+            "DELETE FROM messages WHERE session_id IN (
+                 SELECT id FROM chat_sessions
+                 WHERE deleted_at IS NOT NULL AND deleted_at < ?1 AND (?2 OR trashed_by_retention = 0)
+             )",
+            params![cutoff, include_retention],
+        )?;
+        let purged_sessions = tx.execute(
+            "DELETE FROM chat_sessions
+             WHERE deleted_at IS NOT NULL AND deleted_at < ?1 AND (?2 OR trashed_by_retention = 0)",
+            params![cutoff, include_retention],
+        )?;
+        purged_messages += tx.execute(
+            "DELETE FROM messages WHERE deleted_at IS NOT NULL AND deleted_at < ?",
+            params![cutoff],
+        )?;
+        
+        tx.commit()?;
+        
+        Ok((purged_sessions, purged_messages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // マイグレーション5まで適用した、open_in_memoryと同じ形式のメモリ上のデータベースを作る
+    fn open_v5(uri: &str) -> Connection {
+        let conn = Connection::open(uri).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE chat_sessions (
+                 id TEXT PRIMARY KEY,
+                 title TEXT NOT NULL,
+                 created_at TEXT NOT NULL,
+                 updated_at TEXT NOT NULL
+             );
+             CREATE TABLE messages (
+                 id TEXT PRIMARY KEY,
+                 session_id TEXT NOT NULL,
+                 role TEXT NOT NULL,
+                 content TEXT NOT NULL,
+                 timestamp TEXT NOT NULL,
+                 FOREIGN KEY (session_id) REFERENCES chat_sessions(id)
+             );",
+        ).unwrap();
+        for sql in &MIGRATIONS[..5] {
+            conn.execute_batch(sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 5).unwrap();
+        conn
+    }
+
+    #[test]
+    fn migration_6_numbers_messages_by_timestamp_then_insertion_order() {
+        let uri = format!("file:migration-test-{}?mode=memory&cache=shared", Uuid::new_v4());
+        // データベースを開くまでメモリ上の内容が消えないように接続を残しておく
+        let conn = open_v5(&uri);
+        conn.execute_batch(
+            "INSERT INTO chat_sessions (id, title, created_at, updated_at) VALUES
+                 ('s1', 'one', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00'),
+                 ('s2', 'two', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO messages (id, session_id, role, content, timestamp) VALUES
+                 ('late', 's1', 'assistant', 'late', '2024-01-01T00:00:02+00:00'),
+                 ('tie-1', 's1', 'user', 'tie 1', '2024-01-01T00:00:01+00:00'),
+                 ('other', 's2', 'user', 'other', '2024-01-01T00:00:05+00:00'),
+                 ('tie-2', 's1', 'assistant', 'tie 2', '2024-01-01T00:00:01+00:00'),
+                 ('tie-3', 's1', 'user', 'tie 3', '2024-01-01T00:00:01+00:00');",
+        ).unwrap();
+
+        let database = Database::open_path(Path::new(&uri), None).unwrap();
+
+        let seqs: Vec<(String, i64)> = database.reader()
+            .prepare("SELECT id, seq FROM messages WHERE session_id = 's1' ORDER BY seq").unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?))).unwrap()
+            .collect::<Result<_>>().unwrap();
+        assert_eq!(seqs, [
+            ("tie-1".to_string(), 1),
+            ("tie-2".to_string(), 2),
+            ("tie-3".to_string(), 3),
+            ("late".to_string(), 4),
+        ]);
+
+        // 連番はセッションごとに1から振る
+        let other: i64 = database.reader()
+            .query_row("SELECT seq FROM messages WHERE id = 'other'", [], |row| row.get(0)).unwrap();
+        assert_eq!(other, 1);
+
+        let ids: Vec<String> = database.get_messages("s1").unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["tie-1", "tie-2", "tie-3", "late"]);
+
+        // 移行後に追加したメッセージは、時計が戻っていても末尾に並ぶ
+        let added = database.add_message("s1", "user", "new").unwrap();
+        database.writer()
+            .execute("UPDATE messages SET timestamp = '2023-12-31T00:00:00+00:00' WHERE id = ?", params![added])
+            .unwrap();
+        let ids: Vec<String> = database.get_messages("s1").unwrap().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["tie-1", "tie-2", "tie-3", "late", added.as_str()]);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::config::Config;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+// 1回のリクエストで埋め込みを計算するメッセージ数
+pub const BATCH_SIZE: usize = 32;
+// 長すぎるメッセージは先頭だけを使う（モデルの入力上限を超えないように）
+const MAX_INPUT_CHARS: usize = 6000;
+
+pub type EmbedFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, EmbedError>> + Send + 'a>>;
+
+#[derive(Debug)]
+pub enum EmbedError {
+    // APIに接続できない、混み合っているなど、時間をおけば成功する可能性がある
+    Unavailable(String),
+    // 入力がAPIに拒否された（同じ入力で何度試しても失敗する）
+    Rejected(String),
+}
+
+impl std::fmt::Display for EmbedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmbedError::Unavailable(e) | EmbedError::Rejected(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<EmbedError> for String {
+    fn from(e: EmbedError) -> Self {
+        e.to_string()
+    }
+}
+
+// テキストをベクトルに変換する埋め込みモデル
+// 別の実装（アプリに組み込んだローカルモデルなど）はこのトレイトを実装し、from_configで選択する
+pub trait Embedder: Send + Sync {
+    // 保存したベクトルがどのモデルで計算されたかを区別するための名前
+    fn model(&self) -> &str;
+
+    // textsと同じ順番でベクトルを返す
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a>;
+}
+
+// 設定に従って埋め込みモデルを作成する（セマンティック検索が無効の場合はNone）
+pub fn from_config(config: &Config) -> Option<Box<dyn Embedder>> {
+    if !config.semantic_search {
+        return None;
+    }
+
+    Some(Box::new(OpenAiEmbedder {
+        client: Client::new(),
+        base_url: config.embedding_base_url.trim_end_matches('/').to_string(),
+        api_key: config.embedding_api_key.clone().filter(|key| !key.is_empty()),
+        model: config.embedding_model.clone(),
+    }))
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+// OpenAI互換の埋め込みAPI（/v1/embeddings）
+// OllamaなどローカルのサーバーのURLを指定すれば、メッセージを外部に送らずに使える
+pub struct OpenAiEmbedder {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl OpenAiEmbedder {
+    async fn request(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, EmbedError> {
+        let request = EmbeddingRequest {
+            model: &self.model,
+            input: texts.iter().map(|text| truncate(text)).collect(),
+        };
+
+        let mut builder = self.client
+            .post(format!("{}/v1/embeddings", self.base_url))
+            .json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = builder.send().await
+            .map_err(|e| EmbedError::Unavailable(format!("埋め込みAPIへの接続に失敗しました: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            let message = format!("埋め込みAPI エラー ({}): {}", status.as_u16(), error_text);
+            // 認証エラーや混雑、サーバー側のエラーは入力の問題ではないので、後で計算し直す
+            let transient = status.is_server_error()
+                || matches!(status.as_u16(), 401 | 403 | 404 | 408 | 429);
+            return Err(if transient { EmbedError::Unavailable(message) } else { EmbedError::Rejected(message) });
+        }
+
+        let mut response: EmbeddingResponse = response.json().await
+            .map_err(|e| EmbedError::Unavailable(format!("埋め込みAPIの応答を読み込めませんでした: {}", e)))?;
+        if response.data.len() != texts.len() {
+            return Err(EmbedError::Rejected("埋め込みAPIの応答の件数が一致しません".to_string()));
+        }
+
+        // 応答の順番は保証されていないのでindexで並べ直す
+        response.data.sort_by_key(|data| data.index);
+        Ok(response.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+impl Embedder for OpenAiEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn embed<'a>(&'a self, texts: &'a [String]) -> EmbedFuture<'a> {
+        Box::pin(self.request(texts))
+    }
+}
+
+fn truncate(text: &str) -> &str {
+    match text.char_indices().nth(MAX_INPUT_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+// 長さを1にする（保存するベクトルを正規化しておけば、コサイン類似度は内積で求められる）
+pub fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+    vector
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// SQLiteのBLOBとして保存するためにリトルエンディアンのバイト列にする
+pub fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+pub fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect()
+}
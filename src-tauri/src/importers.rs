@@ -0,0 +1,674 @@
+use crate::database::{ChatSession, ExportData, Message, MessageStatus, EXPORT_VERSION};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Read;
+
+// インポート可能なエクスポート形式のバージョン
+const SUPPORTED_EXPORT_VERSIONS: &[&str] = &[EXPORT_VERSION];
+
+// インポート元の形式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    // このアプリのエクスポート形式
+    Native,
+    // ChatGPTのデータエクスポート（conversations.json）
+    Chatgpt,
+    // Claude.aiのデータエクスポート（conversations.json）
+    Claude,
+    // このアプリのJSON Lines形式（1行ずつ読み込む）
+    Jsonl,
+}
+
+// 既存のデータとIDが重複した場合の扱い
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeStrategy {
+    // 既存の行はそのまま残し、インポートする行をスキップする
+    #[default]
+    Skip,
+    // 既存の行をインポートする内容で上書きする
+    Overwrite,
+    // 新しいIDを割り当てて別の行としてインポートする
+    Copy,
+}
+
+// インポート結果（ドライラン時は作成予定の内容）
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub source: ImportSource,
+    #[serde(rename = "filePath")]
+    pub file_path: String,
+    #[serde(rename = "dryRun")]
+    pub dry_run: bool,
+    pub strategy: MergeStrategy,
+    pub sessions: Vec<ImportedSessionSummary>,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+    #[serde(rename = "skippedMessages")]
+    pub skipped_messages: usize,
+    pub summary: ImportSummary,
+}
+
+// データベースに反映された（ドライラン時は反映される）行数
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ImportSummary {
+    #[serde(rename = "sessionsInserted")]
+    pub sessions_inserted: usize,
+    #[serde(rename = "sessionsUpdated")]
+    pub sessions_updated: usize,
+    #[serde(rename = "sessionsSkipped")]
+    pub sessions_skipped: usize,
+    #[serde(rename = "messagesInserted")]
+    pub messages_inserted: usize,
+    #[serde(rename = "messagesUpdated")]
+    pub messages_updated: usize,
+    #[serde(rename = "messagesSkipped")]
+    pub messages_skipped: usize,
+    // 存在しないセッションを参照していたためスキップしたメッセージ
+    #[serde(rename = "orphanMessages")]
+    pub orphan_messages: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedSessionSummary {
+    pub id: String,
+    pub title: String,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+}
+
+// ファイルを読み込み、共通のExportData形式に変換する
+// sourceがNoneの場合は内容から形式を判定する
+pub fn load(file_path: &str, source: Option<ImportSource>) -> Result<(ImportSource, ExportData, usize), String> {
+    let json = read_json_text(file_path)?;
+    let value: Value = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+
+    let source = match source {
+        Some(source) => source,
+        None => detect_source(&value)?,
+    };
+
+    let (data, skipped) = match source {
+        ImportSource::Native => {
+            let data: ExportData = serde_json::from_value(value)
+                .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+            (data, 0)
+        }
+        ImportSource::Chatgpt => convert_chatgpt(&value)?,
+        ImportSource::Claude => convert_claude(&value)?,
+        ImportSource::Jsonl => return Err("JSON Lines形式は一括で読み込めません".to_string()),
+    };
+
+    Ok((source, data, skipped))
+}
+
+pub fn build_report(
+    source: ImportSource,
+    file_path: &str,
+    dry_run: bool,
+    strategy: MergeStrategy,
+    data: &ExportData,
+    skipped_messages: usize,
+) -> ImportReport {
+    let sessions = data.sessions.iter()
+        .map(|session| ImportedSessionSummary {
+            id: session.id.clone(),
+            title: session.title.clone(),
+            message_count: data.messages.iter().filter(|m| m.session_id == session.id).count(),
+        })
+        .collect();
+
+    ImportReport {
+        source,
+        file_path: file_path.to_string(),
+        dry_run,
+        strategy,
+        sessions,
+        message_count: data.messages.len(),
+        skipped_messages,
+        summary: ImportSummary::default(),
+    }
+}
+
+// バージョンと各行の内容を検証する
+pub fn validate(data: &ExportData) -> Result<(), String> {
+    validate_version(&data.version)?;
+
+    let mut errors = Vec::new();
+
+    let mut session_ids = HashSet::new();
+    for session in &data.sessions {
+        if let Err(e) = validate_session(session) {
+            errors.push(e);
+        } else if !session_ids.insert(session.id.as_str()) {
+            errors.push(format!("セッションIDが重複しています: {}", session.id));
+        }
+    }
+
+    let mut message_ids = HashSet::new();
+    for message in &data.messages {
+        if let Err(e) = validate_message(message) {
+            errors.push(e);
+        } else if !message_ids.insert(message.id.as_str()) {
+            errors.push(format!("メッセージIDが重複しています: {}", message.id));
+        }
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let total = errors.len();
+    let mut message = format!("インポートデータの検証に失敗しました（{}件）:\n", total);
+    message.push_str(&errors.into_iter().take(10).collect::<Vec<_>>().join("\n"));
+    if total > 10 {
+        message.push_str(&format!("\nほか{}件", total - 10));
+    }
+    Err(message)
+}
+
+pub fn validate_version(version: &str) -> Result<(), String> {
+    if SUPPORTED_EXPORT_VERSIONS.contains(&version) {
+        Ok(())
+    } else {
+        Err(format!("サポートされていないエクスポートバージョンです: {}", version))
+    }
+}
+
+pub fn validate_session(session: &ChatSession) -> Result<(), String> {
+    if session.id.trim().is_empty() {
+        return Err(format!("IDが空のセッションがあります: {}", session.title));
+    }
+    if !is_valid_timestamp(&session.created_at) || !is_valid_timestamp(&session.updated_at) {
+        return Err(format!("セッションの日時の形式が正しくありません: {}", session.id));
+    }
+    Ok(())
+}
+
+pub fn validate_message(message: &Message) -> Result<(), String> {
+    if message.id.trim().is_empty() {
+        return Err(format!("IDが空のメッセージがあります（セッション: {}）", message.session_id));
+    }
+    if message.role != "user" && message.role != "assistant" {
+        return Err(format!("メッセージのロールが不正です: {} ({})", message.id, message.role));
+    }
+    if !is_valid_timestamp(&message.timestamp) {
+        return Err(format!("メッセージの日時の形式が正しくありません: {}", message.id));
+    }
+    Ok(())
+}
+
+fn is_valid_timestamp(value: &str) -> bool {
+    DateTime::parse_from_rfc3339(value).is_ok()
+}
+
+// JSONファイル、またはエクスポートアーカイブ（zip）内のconversations.jsonを読み込む
+fn read_json_text(file_path: &str) -> Result<String, String> {
+    let bytes = fs::read(file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    if !bytes.starts_with(b"PK\x03\x04") {
+        return String::from_utf8(bytes)
+            .map_err(|e| format!("Failed to read file: {}", e));
+    }
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|e| format!("Failed to open archive: {}", e))?;
+
+    let entry_name = archive.file_names()
+        .find(|name| name.rsplit('/').next() == Some("conversations.json"))
+        .map(|name| name.to_string())
+        .ok_or("アーカイブ内にconversations.jsonが見つかりません")?;
+
+    let mut entry = archive.by_name(&entry_name)
+        .map_err(|e| format!("Failed to read archive entry: {}", e))?;
+    let mut json = String::new();
+    entry.read_to_string(&mut json)
+        .map_err(|e| format!("Failed to read archive entry: {}", e))?;
+
+    Ok(json)
+}
+
+fn detect_source(value: &Value) -> Result<ImportSource, String> {
+    if value.get("sessions").is_some() && value.get("messages").is_some() {
+        return Ok(ImportSource::Native);
+    }
+
+    if let Some(first) = value.as_array().and_then(|conversations| conversations.first()) {
+        if first.get("mapping").is_some() {
+            return Ok(ImportSource::Chatgpt);
+        }
+        if first.get("chat_messages").is_some() {
+            return Ok(ImportSource::Claude);
+        }
+    }
+
+    Err("インポートファイルの形式を判別できません".to_string())
+}
+
+// UNIX秒（小数あり）をRFC3339に変換する
+fn timestamp_from_secs(secs: Option<f64>) -> Option<String> {
+    let secs = secs?;
+    DateTime::<Utc>::from_timestamp(secs.trunc() as i64, (secs.fract() * 1_000_000_000.0) as u32)
+        .map(|dt| dt.to_rfc3339())
+}
+
+// ISO 8601文字列をこのアプリと同じUTCのRFC3339表現にそろえる
+fn normalize_timestamp(value: Option<&str>) -> Option<String> {
+    DateTime::parse_from_rfc3339(value?)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc).to_rfc3339())
+}
+
+// ChatGPTのconversations.jsonを変換する
+// mappingはメッセージのツリーになっているため、current_nodeから親をたどって表示中の分岐を取り出す
+fn convert_chatgpt(value: &Value) -> Result<(ExportData, usize), String> {
+    let conversations = value.as_array().ok_or("ChatGPTのエクスポート形式ではありません")?;
+    let now = Utc::now().to_rfc3339();
+
+    let mut sessions = Vec::new();
+    let mut messages = Vec::new();
+    let mut skipped = 0;
+
+    for conversation in conversations {
+        let mapping = match conversation.get("mapping").and_then(Value::as_object) {
+            Some(mapping) => mapping,
+            None => continue,
+        };
+
+        let session_id = conversation.get("conversation_id")
+            .or_else(|| conversation.get("id"))
+            .and_then(Value::as_str)
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let created_at = timestamp_from_secs(conversation.get("create_time").and_then(Value::as_f64))
+            .unwrap_or_else(|| now.clone());
+        let updated_at = timestamp_from_secs(conversation.get("update_time").and_then(Value::as_f64))
+            .unwrap_or_else(|| created_at.clone());
+
+        // 表示中の分岐の末尾（なければ子を持たないノード）から根までたどる
+        let leaf = conversation.get("current_node")
+            .and_then(Value::as_str)
+            .map(|id| id.to_string())
+            .or_else(|| {
+                mapping.iter()
+                    .find(|(_, node)| node.get("children").and_then(Value::as_array).is_none_or(|c| c.is_empty()))
+                    .map(|(id, _)| id.clone())
+            });
+
+        let mut thread = Vec::new();
+        let mut cursor = leaf;
+        while let Some(node_id) = cursor {
+            let node = match mapping.get(&node_id) {
+                Some(node) => node,
+                None => break,
+            };
+            thread.push(node);
+            cursor = node.get("parent").and_then(Value::as_str).map(|id| id.to_string());
+            // 循環参照があっても無限ループしないようにする
+            if thread.len() > mapping.len() {
+                break;
+            }
+        }
+        thread.reverse();
+
+        let mut session_messages = Vec::new();
+        for node in thread {
+            let message = match node.get("message") {
+                Some(message) if !message.is_null() => message,
+                _ => continue,
+            };
+
+            let role = message.pointer("/author/role").and_then(Value::as_str).unwrap_or("");
+            if role != "user" && role != "assistant" {
+                skipped += 1;
+                continue;
+            }
+
+            let content = chatgpt_content_text(message.get("content"));
+            if content.trim().is_empty() {
+                skipped += 1;
+                continue;
+            }
+
+            session_messages.push(Message {
+                id: message.get("id")
+                    .and_then(Value::as_str)
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+                session_id: session_id.clone(),
+                role: role.to_string(),
+                content,
+                timestamp: timestamp_from_secs(message.get("create_time").and_then(Value::as_f64))
+                    .unwrap_or_else(|| created_at.clone()),
+                status: MessageStatus::Complete,
+            });
+        }
+
+        if session_messages.is_empty() {
+            continue;
+        }
+
+        sessions.push(ChatSession {
+            id: session_id,
+            title: conversation.get("title")
+                .and_then(Value::as_str)
+                .filter(|title| !title.is_empty())
+                .unwrap_or("ChatGPTからインポート")
+                .to_string(),
+            created_at,
+            updated_at,
+            ..Default::default()
+        });
+        messages.extend(session_messages);
+    }
+
+    Ok((
+        ExportData {
+            sessions,
+            messages,
+            version: EXPORT_VERSION.to_string(),
+            exported_at: now,
+        },
+        skipped,
+    ))
+}
+
+// ChatGPTのcontentからテキスト部分だけを取り出す（画像などは無視する）
+fn chatgpt_content_text(content: Option<&Value>) -> String {
+    let content = match content {
+        Some(content) => content,
+        None => return String::new(),
+    };
+
+    if let Some(parts) = content.get("parts").and_then(Value::as_array) {
+        return parts.iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    content.get("text")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_string()
+}
+
+// Claude.aiのconversations.jsonを変換する
+fn convert_claude(value: &Value) -> Result<(ExportData, usize), String> {
+    let conversations = value.as_array().ok_or("Claude.aiのエクスポート形式ではありません")?;
+    let now = Utc::now().to_rfc3339();
+
+    let mut sessions = Vec::new();
+    let mut messages = Vec::new();
+    let mut skipped = 0;
+
+    for conversation in conversations {
+        let chat_messages = match conversation.get("chat_messages").and_then(Value::as_array) {
+            Some(chat_messages) => chat_messages,
+            None => continue,
+        };
+
+        let session_id = conversation.get("uuid")
+            .and_then(Value::as_str)
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let created_at = normalize_timestamp(conversation.get("created_at").and_then(Value::as_str))
+            .unwrap_or_else(|| now.clone());
+        let updated_at = normalize_timestamp(conversation.get("updated_at").and_then(Value::as_str))
+            .unwrap_or_else(|| created_at.clone());
+
+        let mut session_messages = Vec::new();
+        for message in chat_messages {
+            let role = match message.get("sender").and_then(Value::as_str) {
+                Some("human") => "user",
+                Some("assistant") => "assistant",
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+
+            let content = claude_content_text(message);
+            if content.trim().is_empty() {
+                skipped += 1;
+                continue;
+            }
+
+            session_messages.push(Message {
+                id: message.get("uuid")
+                    .and_then(Value::as_str)
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+                session_id: session_id.clone(),
+                role: role.to_string(),
+                content,
+                timestamp: normalize_timestamp(message.get("created_at").and_then(Value::as_str))
+                    .unwrap_or_else(|| created_at.clone()),
+                status: MessageStatus::Complete,
+            });
+        }
+
+        if session_messages.is_empty() {
+            continue;
+        }
+
+        sessions.push(ChatSession {
+            id: session_id,
+            title: conversation.get("name")
+                .and_then(Value::as_str)
+                .filter(|name| !name.is_empty())
+                .unwrap_or("Claudeからインポート")
+                .to_string(),
+            created_at,
+            updated_at,
+            ..Default::default()
+        });
+        messages.extend(session_messages);
+    }
+
+    Ok((
+        ExportData {
+            sessions,
+            messages,
+            version: EXPORT_VERSION.to_string(),
+            exported_at: now,
+        },
+        skipped,
+    ))
+}
+
+// Claude.aiのメッセージ本文を取り出す（contentブロックがあればそのtext部分を優先する）
+fn claude_content_text(message: &Value) -> String {
+    if let Some(blocks) = message.get("content").and_then(Value::as_array) {
+        let text = blocks.iter()
+            .filter(|block| block.get("type").and_then(Value::as_str) == Some("text"))
+            .filter_map(|block| block.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !text.is_empty() {
+            return text;
+        }
+    }
+
+    message.get("text")
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chatgpt_fixture() -> Value {
+        // 2つ目の応答（n4）は表示中の分岐ではないので取り込まない
+        json!([
+            {
+                "title": "Rustの質問",
+                "conversation_id": "conv-1",
+                "create_time": 1700000000.5,
+                "update_time": 1700000100.0,
+                "current_node": "n3",
+                "mapping": {
+                    "root": { "id": "root", "message": null, "parent": null, "children": ["n0"] },
+                    "n0": {
+                        "id": "n0",
+                        "message": {
+                            "id": "m0",
+                            "author": { "role": "system" },
+                            "content": { "content_type": "text", "parts": ["You are helpful."] }
+                        },
+                        "parent": "root",
+                        "children": ["n1"]
+                    },
+                    "n1": {
+                        "id": "n1",
+                        "message": {
+                            "id": "m1",
+                            "author": { "role": "user" },
+                            "create_time": 1700000010.0,
+                            "content": { "content_type": "text", "parts": ["所有権とは？", "簡単に"] }
+                        },
+                        "parent": "n0",
+                        "children": ["n2"]
+                    },
+                    "n2": {
+                        "id": "n2",
+                        "message": {
+                            "id": "m2",
+                            "author": { "role": "assistant" },
+                            "create_time": 1700000020.0,
+                            "content": { "content_type": "text", "parts": [""] }
+                        },
+                        "parent": "n1",
+                        "children": ["n3", "n4"]
+                    },
+                    "n3": {
+                        "id": "n3",
+                        "message": {
+                            "id": "m3",
+                            "author": { "role": "assistant" },
+                            "create_time": 1700000030.0,
+                            "content": { "content_type": "text", "parts": ["値の持ち主は1つです。"] }
+                        },
+                        "parent": "n2",
+                        "children": []
+                    },
+                    "n4": {
+                        "id": "n4",
+                        "message": {
+                            "id": "m4",
+                            "author": { "role": "assistant" },
+                            "create_time": 1700000040.0,
+                            "content": { "content_type": "text", "parts": ["別の回答"] }
+                        },
+                        "parent": "n2",
+                        "children": []
+                    }
+                }
+            },
+            {
+                "title": "",
+                "conversation_id": "conv-empty",
+                "mapping": {
+                    "root": { "id": "root", "message": null, "parent": null, "children": [] }
+                }
+            }
+        ])
+    }
+
+    fn claude_fixture() -> Value {
+        json!([
+            {
+                "uuid": "claude-1",
+                "name": "",
+                "created_at": "2024-03-01T09:00:00+09:00",
+                "updated_at": "2024-03-01T09:05:00.123Z",
+                "chat_messages": [
+                    {
+                        "uuid": "c1",
+                        "sender": "human",
+                        "text": "こんにちは",
+                        "created_at": "2024-03-01T00:00:01Z"
+                    },
+                    {
+                        "uuid": "c2",
+                        "sender": "assistant",
+                        "text": "古い本文",
+                        "content": [
+                            { "type": "text", "text": "こんにちは！" },
+                            { "type": "tool_use", "name": "search" },
+                            { "type": "text", "text": "何をしましょう？" }
+                        ]
+                    },
+                    { "uuid": "c3", "sender": "system", "text": "ignored" },
+                    { "uuid": "c4", "sender": "human", "text": "  " }
+                ]
+            }
+        ])
+    }
+
+    #[test]
+    fn detect_source_recognizes_exports() {
+        assert_eq!(detect_source(&chatgpt_fixture()).unwrap(), ImportSource::Chatgpt);
+        assert_eq!(detect_source(&claude_fixture()).unwrap(), ImportSource::Claude);
+        assert_eq!(detect_source(&json!({ "sessions": [], "messages": [] })).unwrap(), ImportSource::Native);
+        assert!(detect_source(&json!([])).is_err());
+    }
+
+    #[test]
+    fn convert_chatgpt_follows_current_branch() {
+        let (data, skipped) = convert_chatgpt(&chatgpt_fixture()).unwrap();
+
+        // 会話の無いセッションは作らない
+        assert_eq!(data.sessions.len(), 1);
+        let session = &data.sessions[0];
+        assert_eq!(session.id, "conv-1");
+        assert_eq!(session.title, "Rustの質問");
+        assert_eq!(session.created_at, "2023-11-14T22:13:20.500+00:00");
+        assert_eq!(session.updated_at, "2023-11-14T22:15:00+00:00");
+
+        let messages: Vec<(&str, &str, &str)> = data.messages.iter()
+            .map(|m| (m.id.as_str(), m.role.as_str(), m.content.as_str()))
+            .collect();
+        assert_eq!(messages, [
+            ("m1", "user", "所有権とは？\n簡単に"),
+            ("m3", "assistant", "値の持ち主は1つです。"),
+        ]);
+        assert!(data.messages.iter().all(|m| m.session_id == "conv-1" && m.status == MessageStatus::Complete));
+        assert_eq!(data.messages[0].timestamp, "2023-11-14T22:13:30+00:00");
+        // systemのメッセージと空の応答
+        assert_eq!(skipped, 2);
+    }
+
+    #[test]
+    fn convert_claude_prefers_text_blocks() {
+        let (data, skipped) = convert_claude(&claude_fixture()).unwrap();
+
+        assert_eq!(data.sessions.len(), 1);
+        let session = &data.sessions[0];
+        assert_eq!(session.id, "claude-1");
+        assert_eq!(session.title, "Claudeからインポート");
+        assert_eq!(session.created_at, "2024-03-01T00:00:00+00:00");
+        assert_eq!(session.updated_at, "2024-03-01T09:05:00.123+00:00");
+
+        let messages: Vec<(&str, &str, &str, &str)> = data.messages.iter()
+            .map(|m| (m.id.as_str(), m.role.as_str(), m.content.as_str(), m.timestamp.as_str()))
+            .collect();
+        assert_eq!(messages, [
+            ("c1", "user", "こんにちは", "2024-03-01T00:00:01+00:00"),
+            // 時刻が無いメッセージはセッションの作成日時にする
+            ("c2", "assistant", "こんにちは！\n何をしましょう？", "2024-03-01T00:00:00+00:00"),
+        ]);
+        // systemのメッセージと空のメッセージ
+        assert_eq!(skipped, 2);
+        assert!(validate(&data).is_ok());
+    }
+}
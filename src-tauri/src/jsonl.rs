@@ -0,0 +1,46 @@
+use crate::database::{ChatSession, Message};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+// 進捗イベントを送る間隔（行数）
+pub const PROGRESS_INTERVAL: usize = 1000;
+
+// JSON Lines形式の1行分のレコード
+// 先頭行がheaderで、その後にセッションとそのセッションのメッセージが順に並ぶ
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum JsonlRecord {
+    Header {
+        version: String,
+        #[serde(rename = "exportedAt")]
+        exported_at: String,
+    },
+    Session(ChatSession),
+    Message(Message),
+}
+
+// エクスポート/インポートの進捗
+// エクスポートではメッセージ数、インポートではファイルのバイト数を単位とする
+#[derive(Debug, Serialize, Clone)]
+pub struct TransferProgress {
+    pub processed: u64,
+    pub total: u64,
+}
+
+// ファイルの先頭行がJSON Linesのヘッダーかどうかを判定する
+pub fn is_jsonl_file(file_path: &str) -> Result<bool, String> {
+    let file = File::open(file_path)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    // 先頭行だけを読む（巨大な1行JSONを丸ごと読み込まないよう上限を設ける）
+    let mut first_line = String::new();
+    BufReader::new(file.take(64 * 1024))
+        .read_line(&mut first_line)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+
+    Ok(matches!(
+        serde_json::from_str::<JsonlRecord>(first_line.trim()),
+        Ok(JsonlRecord::Header { .. })
+    ))
+}
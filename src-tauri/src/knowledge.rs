@@ -0,0 +1,172 @@
+use crate::database::KnowledgeChunk;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+// 取り込む資料の拡張子
+const SUPPORTED_EXTENSIONS: &[&str] = &["md", "markdown", "txt", "pdf"];
+// 1つのチャンクの目安の文字数（段落の区切りで分け、長すぎる段落は途中で切る）
+const CHUNK_CHARS: usize = 1000;
+// 質問に添えるチャンクの最大数
+pub const CHUNK_LIMIT: usize = 5;
+// これより類似度の低いチャンクは関係がないものとして使わない
+const MIN_SCORE: f32 = 0.3;
+
+// 取り込みの結果
+#[derive(Debug, Serialize, Default)]
+pub struct IngestReport {
+    pub added: usize,
+    // 内容が変わった（または埋め込みモデルが変わった）ため取り込み直した資料
+    pub updated: usize,
+    pub unchanged: usize,
+    // フォルダから無くなったため削除した資料
+    pub removed: usize,
+    // 今回作成したチャンクの数
+    pub chunks: usize,
+    pub failed: Vec<IngestFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestFailure {
+    pub path: String,
+    pub error: String,
+}
+
+// 取り込みの進捗（knowledge-ingest-progressイベントで送る）
+#[derive(Debug, Serialize, Clone)]
+pub struct IngestProgress {
+    #[serde(rename = "collectionId")]
+    pub collection_id: String,
+    pub processed: usize,
+    pub total: usize,
+}
+
+// フォルダ以下の取り込み対象のファイルを探す（隠しファイルとフォルダは除く）
+// 読み込めなかったファイルやフォルダは飛ばし、取り込みの失敗として返す
+pub fn scan(folder: &Path) -> Result<(Vec<PathBuf>, Vec<IngestFailure>), String> {
+    if !folder.is_dir() {
+        return Err(format!("フォルダが見つかりません: {}", folder.display()));
+    }
+
+    let mut files = Vec::new();
+    let mut failures = Vec::new();
+    let walker = WalkDir::new(folder)
+        .follow_links(true)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !entry.file_name().to_string_lossy().starts_with('.'));
+    for entry in walker {
+        match entry {
+            Ok(entry) => {
+                if entry.file_type().is_file() && is_supported(entry.path()) {
+                    files.push(entry.into_path());
+                }
+            }
+            Err(e) => failures.push(IngestFailure {
+                path: e.path().map(|path| relative_path(folder, path)).unwrap_or_default(),
+                error: format!("フォルダの読み込みに失敗しました: {}", e),
+            }),
+        }
+    }
+    files.sort();
+
+    Ok((files, failures))
+}
+
+// pathがdirそのものか、dirの下にあるか（dirが空の場合はコレクション全体を指す）
+pub fn is_under(path: &str, dir: &str) -> bool {
+    dir.is_empty() || path == dir || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+// コレクションのフォルダからの相対パス（区切りは/に揃える）
+pub fn relative_path(folder: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(folder).unwrap_or(path);
+    relative.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// ファイルの内容のハッシュ（再取り込み時に変更されたファイルを見つけるため）
+pub fn hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// ファイルからテキストを取り出す
+pub fn extract_text(path: &Path, bytes: &[u8]) -> Result<String, String> {
+    let is_pdf = path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+    if !is_pdf {
+        return Ok(String::from_utf8_lossy(bytes).into_owned());
+    }
+
+    // 壊れたPDFではpdf-extractがパニックすることがあるので、取り込みの失敗として扱う
+    std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+        .map_err(|_| "PDFを解析できませんでした".to_string())?
+        .map_err(|e| format!("PDFからテキストを取り出せませんでした: {}", e))
+}
+
+// ファイルを読み込み、ハッシュを返す
+pub fn read(path: &Path) -> Result<(Vec<u8>, String), String> {
+    let bytes = fs::read(path).map_err(|e| format!("ファイルを読み込めませんでした: {}", e))?;
+    let hash = hash(&bytes);
+    Ok((bytes, hash))
+}
+
+// テキストを段落の区切りでチャンクに分ける
+pub fn chunk(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for paragraph in text.split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() > CHUNK_CHARS {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+
+        // 1つの段落が長すぎる場合は途中で切る
+        while let Some((end, _)) = current.char_indices().nth(CHUNK_CHARS) {
+            let rest = current.split_off(end);
+            chunks.push(std::mem::replace(&mut current, rest));
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+// 類似度が十分に高いチャンクだけを残す
+pub fn relevant(chunks: Vec<KnowledgeChunk>) -> Vec<KnowledgeChunk> {
+    chunks.into_iter().filter(|chunk| chunk.score >= MIN_SCORE).collect()
+}
+
+// 資料の抜粋を出典付きで質問の前に付ける
+pub fn build_prompt(content: &str, chunks: &[KnowledgeChunk]) -> String {
+    if chunks.is_empty() {
+        return content.to_string();
+    }
+
+    let mut prompt = String::from(
+        "以下はユーザーが添付した資料からの抜粋です。質問に関係がある場合は参考にし、利用した資料の名前を示してください。\n\n"
+    );
+    for chunk in chunks {
+        prompt.push_str(&format!("[資料: {} / {}]\n{}\n\n", chunk.collection_name, chunk.path, chunk.content));
+    }
+    prompt.push_str("---\n\n");
+    prompt.push_str(content);
+
+    prompt
+}
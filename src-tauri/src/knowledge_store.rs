@@ -0,0 +1,179 @@
+use crate::database::{KnowledgeChunk, KnowledgeDocument};
+use crate::embeddings;
+use crate::profile;
+use chrono::Utc;
+use rusqlite::{params, Connection, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+// 資料のチャンクと埋め込みは、コレクションごとにプロファイルのデータディレクトリのknowledgeに置く
+// チャット履歴のデータベースとは別のファイルにして、バックアップや暗号化、VACUUMで資料全体を複製しないようにする
+// （元のファイルはコレクションのフォルダにあるので、このファイルが無くなっても取り込み直せる）
+const STORE_DIR: &str = "knowledge";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS documents (
+        id TEXT PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        hash TEXT NOT NULL,
+        ingested_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS chunks (
+        id TEXT PRIMARY KEY,
+        document_id TEXT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        content TEXT NOT NULL,
+        model TEXT NOT NULL,
+        vector BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_chunks_document ON chunks(document_id);";
+
+// コレクションの資料の保存先のファイル
+pub fn store_path(app_handle: &tauri::AppHandle, collection_id: &str) -> std::result::Result<PathBuf, String> {
+    let dir = profile::data_dir_of(app_handle, &profile::active(app_handle)?)?.join(STORE_DIR);
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create knowledge directory: {}", e))?;
+    Ok(dir.join(format!("{}.db", collection_id)))
+}
+
+// コレクションの資料の保存先を削除する
+pub fn delete(app_handle: &tauri::AppHandle, collection_id: &str) -> std::result::Result<(), String> {
+    let path = store_path(app_handle, collection_id)?;
+    for path in [path.clone(), path.with_extension("db-wal"), path.with_extension("db-shm")] {
+        if path.exists() {
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove knowledge store: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+pub struct KnowledgeStore {
+    conn: Connection,
+}
+
+impl KnowledgeStore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    // 取り込んだ資料とチャンクの数
+    pub fn counts(&self) -> Result<(usize, usize)> {
+        self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM documents), (SELECT COUNT(*) FROM chunks)",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+
+    // 取り込み済みの資料（modelは現在の埋め込みモデル）
+    pub fn get_documents(&self, model: &str) -> Result<Vec<KnowledgeDocument>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.path, d.hash,
+                 NOT EXISTS (SELECT 1 FROM chunks k WHERE k.document_id = d.id AND k.model != ?)
+             FROM documents d"
+        )?;
+
+        let documents = stmt.query_map(params![model], |row| {
+            Ok(KnowledgeDocument {
+                path: row.get(0)?,
+                hash: row.get(1)?,
+                up_to_date: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(documents)
+    }
+
+    // 資料のチャンクと埋め込みを保存する（同じパスの資料が取り込み済みなら置き換える）
+    pub fn save_document(&mut self, path: &str, hash: &str, model: &str, chunks: &[(String, Vec<f32>)]) -> Result<()> {
+        let document_id = Uuid::new_v4().to_string();
+        let tx = self.conn.transaction()?;
+
+        tx.execute("DELETE FROM documents WHERE path = ?", params![path])?;
+        tx.execute(
+            "INSERT INTO documents (id, path, hash, ingested_at) VALUES (?, ?, ?, ?)",
+            params![document_id, path, hash, Utc::now().to_rfc3339()],
+        )?;
+        for (position, (content, vector)) in chunks.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO chunks (id, document_id, position, content, model, vector) VALUES (?, ?, ?, ?, ?, ?)",
+                params![Uuid::new_v4().to_string(), document_id, position as i64, content, model, embeddings::to_blob(vector)],
+            )?;
+        }
+
+        tx.commit()
+    }
+
+    // フォルダから無くなった資料を削除する
+    pub fn remove_documents(&mut self, paths: &[String]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+
+        for path in paths {
+            tx.execute("DELETE FROM documents WHERE path = ?", params![path])?;
+        }
+
+        tx.commit()
+    }
+
+    // 正規化したクエリのベクトルに近いチャンクを、類似度の高い順にlimit件返す
+    pub fn search(&self, collection_name: &str, model: &str, query: &[f32], limit: usize) -> Result<Vec<KnowledgeChunk>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.path, k.content, k.vector
+             FROM chunks k
+             JOIN documents d ON d.id = k.document_id
+             WHERE k.model = ?"
+        )?;
+        let mut rows = stmt.query(params![model])?;
+
+        let mut results: Vec<KnowledgeChunk> = Vec::with_capacity(limit + 1);
+        while let Some(row) = rows.next()? {
+            let vector: Vec<u8> = row.get(2)?;
+            let score = embeddings::dot(query, &embeddings::from_blob(&vector));
+
+            if results.len() >= limit && results.last().is_some_and(|last| last.score >= score) {
+                continue;
+            }
+
+            results.push(KnowledgeChunk {
+                collection_name: collection_name.to_string(),
+                path: row.get(0)?,
+                content: row.get(1)?,
+                score,
+            });
+            results.sort_by(|a, b| b.score.total_cmp(&a.score));
+            results.truncate(limit);
+        }
+
+        Ok(results)
+    }
+}
+
+// 複数のコレクション（IDと名前）から、クエリに近いチャンクを類似度の高い順にlimit件返す
+// まだ取り込んでいないコレクションは空として扱う
+pub fn search(
+    app_handle: &tauri::AppHandle,
+    collections: &[(String, String)],
+    model: &str,
+    query: &[f32],
+    limit: usize,
+) -> std::result::Result<Vec<KnowledgeChunk>, String> {
+    let mut results = Vec::new();
+    for (collection_id, name) in collections {
+        let path = store_path(app_handle, collection_id)?;
+        if !path.exists() {
+            continue;
+        }
+        let store = KnowledgeStore::open(&path).map_err(|e| e.to_string())?;
+        results.extend(store.search(name, model, query, limit).map_err(|e| e.to_string())?);
+    }
+
+    results.sort_by(|a, b| b.score.total_cmp(&a.score));
+    results.truncate(limit);
+    Ok(results)
+}
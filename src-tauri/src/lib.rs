@@ -1,18 +1,104 @@
 mod mcp;
 mod database;
 mod config;
+mod transcript;
+mod importers;
+mod jsonl;
+mod backup;
+mod embeddings;
+mod recall;
+mod knowledge;
+mod knowledge_store;
+mod profile;
 
 use mcp::McpClient;
 use database::Database;
-use config::{Config, load_config, save_config};
-use tauri::{State, Manager};
-use std::sync::Mutex;
+use config::{Config, ConfigError, load_config, save_config};
+use transcript::ExportFormat;
+use importers::{ImportReport, ImportSource, MergeStrategy};
+use backup::BackupInfo;
+use embeddings::EmbedError;
+use tauri::{State, Manager, Emitter};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tauri_plugin_dialog::DialogExt;
 
 struct AppState {
     mcp_client: Mutex<Option<McpClient>>,
-    database: Mutex<Option<Database>>,
+    // 各処理はArcを複製してから使うので、このロックはすぐに解放される
+    database: Mutex<Option<Arc<Database>>>,
     config: Mutex<Config>,
+    // 自動ロック用の最後に操作があった時刻
+    last_activity: Mutex<Instant>,
+    // 起動時（またはプロファイルの切り替え時）に見つかった問題
+    diagnostics: Mutex<StartupDiagnostics>,
+}
+
+impl AppState {
+    fn database(&self) -> Result<Arc<Database>, String> {
+        self.database.lock().unwrap().clone().ok_or_else(|| "Database not initialized".to_string())
+    }
+    
+    // 自動ロックまでの時間を延ばす
+    fn record_activity(&self) {
+        *self.last_activity.lock().unwrap() = Instant::now();
+    }
+}
+
+// ブロッキングする処理を非同期ランタイムのスレッドとは別のスレッドで実行する
+async fn run_blocking<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("Background task failed: {}", e))?
+}
+
+// データベースの処理を別のスレッドで実行する
+// 読み込みは接続プールの別々の接続で行われるため、長いエクスポートの間もメッセージの送信は止まらない
+// コマンドからのデータベースの操作は、自動ロックの判定では操作があったものとして扱う
+async fn with_database<T, F>(state: &AppState, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> Result<T, String> + Send + 'static,
+{
+    state.record_activity();
+    with_database_in_background(state, f).await
+}
+
+// バックグラウンドの処理用（操作があったものとして扱わないので、自動ロックを妨げない）
+async fn with_database_in_background<T, F>(state: &AppState, f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> Result<T, String> + Send + 'static,
+{
+    let database = state.database()?;
+    run_blocking(move || f(&database)).await
+}
+
+// 実行中の処理が終わるのを待ってからデータベースを取り出す（ファイルを置き換える操作で使う）
+// 取り出している間、他のコマンドからは初期化されていないように見える
+fn take_database(state: &AppState) -> Result<Database, String> {
+    let mut database = state.database.lock().unwrap().take().ok_or("Database not initialized")?;
+    let deadline = Instant::now() + Duration::from_secs(30);
+    
+    loop {
+        match Arc::try_unwrap(database) {
+            Ok(database) => return Ok(database),
+            Err(shared) if Instant::now() < deadline => {
+                database = shared;
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(shared) => {
+                *state.database.lock().unwrap() = Some(shared);
+                return Err("データベースが使用中です。しばらくしてからもう一度お試しください".to_string());
+            }
+        }
+    }
 }
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
@@ -45,8 +131,10 @@ async fn initialize_mcp(
 async fn send_message(
     content: String,
     session_id: String,
+    recall: Option<bool>,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+    app_handle: tauri::AppHandle,
+) -> Result<database::ChatTurn, String> {
     // 非同期処理を実行する前に、クライアントが初期化されているか確認
     {
         let guard = state.mcp_client.lock().unwrap();
@@ -55,35 +143,206 @@ async fn send_message(
         }
     }
     
-    // 現在のセッションの過去のメッセージを取得
-    let mut messages = Vec::new();
-    {
-        let database_guard = state.database.lock().unwrap();
-        let database = database_guard.as_ref().ok_or("Database not initialized")?;
-        
-        // 過去のメッセージを取得（最大10件）
-        let past_messages = database.get_messages(&session_id)
-            .map_err(|e| e.to_string())?;
-        
-        // 過去のメッセージをMCP形式に変換
-        for msg in past_messages.iter().rev().take(10).rev() {
-            messages.push(mcp::Message {
-                role: msg.role.clone(),
-                content: msg.content.clone(),
-            });
+    // ユーザーメッセージと応答用のプレースホルダーを保存し、過去のメッセージを取得
+    // 以降の処理が失敗してもメッセージが失われないよう、最初に保存する
+    let turn = {
+        let session_id = session_id.clone();
+        let content = content.clone();
+        with_database(&state, move |database| {
+            database.begin_turn(&session_id, &content)
+                .map_err(|e| e.to_string())
+        }).await?
+    };
+    
+    // 思い出しモードでは他のセッションから、資料を添付したセッションでは資料から関連する内容を探す
+    // 探せなかった場合は、応答の代わりにエラーをプレースホルダーに書き込む
+    let (citations, chunks) = match retrieve_context(&state, &app_handle, &session_id, &content, recall.unwrap_or(false)).await {
+        Ok(context) => context,
+        Err(e) => {
+            let message_id = turn.assistant_message_id.clone();
+            let error = e.clone();
+            with_database(&state, move |database| {
+                database.finish_turn(&message_id, &error, database::MessageStatus::Error, None)
+                    .map_err(|e| e.to_string())
+            }).await?;
+            return Err(e);
         }
+    };
+    
+    // まだアシスタントの応答がなければ最初のやり取り
+    let is_first_exchange = turn.history.iter().all(|msg| msg.role != "assistant");
+    
+    // 過去のメッセージ（今回のメッセージを含めて最大10件）をMCP形式に変換
+    let mut messages = Vec::new();
+    for msg in turn.history.iter().rev().take(10).rev() {
+        messages.push(mcp::Message {
+            role: msg.role.clone(),
+            content: msg.content.clone(),
+        });
     }
     
-    // 新しいユーザーメッセージを追加
-    messages.push(mcp::Message {
-        role: "user".to_string(),
-        content: content.clone(),
-    });
+    // 今回のメッセージにだけ抜粋を添える
+    if let Some(last) = messages.last_mut() {
+        last.content = knowledge::build_prompt(&recall::build_prompt(&content, &citations), &chunks);
+    }
+    
+    // どの過去のメッセージを使ったかを応答に記録する
+    if !citations.is_empty() {
+        let message_id = turn.assistant_message_id.clone();
+        let citations = citations.clone();
+        with_database(&state, move |database| {
+            database.save_citations(&message_id, &citations)
+                .map_err(|e| e.to_string())
+        }).await?;
+    }
+    
+    // 応答を生成したモデルを記録する
+    let model = state.mcp_client.lock().unwrap().as_ref().map(|client| client.get_model());
     
     // 各メッセージを個別に処理する関数を作成
-    let response = process_message_with_mcp(&state, messages).await?;
+    let result = process_message_with_mcp(&state, messages).await;
+    
+    // 応答またはエラーをプレースホルダーに書き込む
+    let (reply, status) = match &result {
+        Ok(response) => (response.clone(), database::MessageStatus::Complete),
+        Err(e) => (e.clone(), database::MessageStatus::Error),
+    };
+    let assistant_message = {
+        let message_id = turn.assistant_message_id.clone();
+        with_database(&state, move |database| {
+            database.finish_turn(&message_id, &reply, status, model.as_deref())
+                .map_err(|e| e.to_string())
+        }).await?
+    };
+    let response = result?;
+    
+    // 最初のやり取りの後、バックグラウンドでタイトルを生成
+    let auto_title = state.config.lock().unwrap().auto_title;
+    if is_first_exchange && auto_title {
+        spawn_title_generation(app_handle, session_id, content, response);
+    }
+    
+    Ok(database::ChatTurn {
+        user_message: turn.user_message,
+        assistant_message,
+        citations,
+    })
+}
+
+// 質問と意味の近い内容を探す
+// 思い出しモードでは他のセッションのメッセージを引用として、セッションに資料が添付されていれば資料のチャンクを返す
+async fn retrieve_context(
+    state: &State<'_, AppState>,
+    app_handle: &tauri::AppHandle,
+    session_id: &str,
+    content: &str,
+    recall: bool,
+) -> Result<(Vec<database::Citation>, Vec<database::KnowledgeChunk>), String> {
+    let collection_ids = {
+        let session_id = session_id.to_string();
+        with_database(state, move |database| {
+            database.get_session_collections(&session_id)
+                .map_err(|e| e.to_string())
+        }).await?
+    };
+    if !recall && collection_ids.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+    
+    let config = state.config.lock().unwrap().clone();
+    let embedder = embeddings::from_config(&config)
+        .ok_or("過去のチャットや資料を参照するには、設定でセマンティック検索を有効にしてください")?;
+    
+    let vector = embedder.embed(&[content.to_string()]).await?
+        .pop()
+        .map(embeddings::normalize)
+        .ok_or("埋め込みAPIの応答がありません")?;
+    let model = embedder.model().to_string();
+    let session_id = session_id.to_string();
+    let app_handle = app_handle.clone();
     
-    Ok(response)
+    with_database(state, move |database| {
+        let citations = if recall {
+            let results = database.semantic_search(&model, &vector, recall::RECALL_LIMIT, Some(&session_id))
+                .map_err(|e| e.to_string())?;
+            recall::citations_from(results)
+        } else {
+            Vec::new()
+        };
+        
+        let collections: Vec<(String, String)> = database.get_collections()
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|collection| collection_ids.contains(&collection.id))
+            .map(|collection| (collection.id, collection.name))
+            .collect();
+        let chunks = knowledge_store::search(&app_handle, &collections, &model, &vector, knowledge::CHUNK_LIMIT)?;
+        
+        Ok((citations, knowledge::relevant(chunks)))
+    }).await
+}
+
+// セッションのタイトルが更新されたときに送るイベントのペイロード
+#[derive(Clone, serde::Serialize)]
+struct SessionTitleUpdated {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    title: String,
+}
+
+// 最初のやり取りからモデルに短いタイトルを付けてもらい、セッションのタイトルを更新する
+fn spawn_title_generation(app_handle: tauri::AppHandle, session_id: String, user_content: String, reply: String) {
+    tauri::async_runtime::spawn(async move {
+        let state = app_handle.state::<AppState>();
+        
+        let prompt = format!(
+            "次の会話に、内容を表す短いタイトル（30文字以内）を付けてください。タイトルのみを出力してください。\n\nユーザー: {}\n\nアシスタント: {}",
+            user_content, reply
+        );
+        let messages = vec![mcp::Message {
+            role: "user".to_string(),
+            content: prompt,
+        }];
+        
+        let title = match process_message_with_mcp(&state, messages).await {
+            Ok(title) => title,
+            Err(e) => {
+                eprintln!("Failed to generate session title: {}", e);
+                return;
+            }
+        };
+        
+        // 余分な引用符や改行を取り除き、長すぎる場合は切り詰める
+        let title: String = title
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim()
+            .trim_matches(|c| c == '"' || c == '「' || c == '」')
+            .chars()
+            .take(50)
+            .collect();
+        if title.is_empty() {
+            return;
+        }
+        
+        let updated = {
+            let session_id = session_id.clone();
+            let title = title.clone();
+            with_database(&state, move |database| {
+                database.set_generated_title(&session_id, &title)
+                    .map_err(|e| e.to_string())
+            }).await
+        };
+        
+        match updated {
+            Ok(true) => {
+                let _ = app_handle.emit("session-title-updated", SessionTitleUpdated { session_id, title });
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("Failed to update session title: {}", e),
+        }
+    });
 }
 
 // MutexGuardの問題を回避するためのヘルパー関数
@@ -110,49 +369,1146 @@ async fn process_message_with_mcp(
         .map_err(|e| e.to_string())
 }
 
+// 設定に従って保持ポリシーを適用する
+fn apply_retention(database: &Database, config: &Config) -> Result<usize, String> {
+    database.apply_retention(config.max_history, config.retention_max_age_days)
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
-fn create_chat_session(title: String, state: State<'_, AppState>) -> Result<String, String> {
-    let database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_ref().ok_or("Database not initialized")?;
+async fn create_chat_session(title: String, state: State<'_, AppState>) -> Result<String, String> {
+    let config = state.config.lock().unwrap().clone();
     
-    database.create_session(&title)
-        .map_err(|e| e.to_string())
+    with_database(&state, move |database| {
+        let session_id = database.create_session(&title)
+            .map_err(|e| e.to_string())?;
+        
+        // 新しいセッションで上限を超えた分をゴミ箱に移動
+        apply_retention(database, &config)?;
+        
+        Ok(session_id)
+    }).await
+}
+
+#[tauri::command]
+async fn rename_chat_session(session_id: String, title: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.rename_session(&session_id, &title)
+            .map_err(|e| e.to_string())
+    }).await
 }
 
 #[tauri::command]
-fn get_chat_sessions(state: State<'_, AppState>) -> Result<Vec<database::ChatSession>, String> {
-    let database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_ref().ok_or("Database not initialized")?;
+async fn get_chat_sessions(query: Option<database::SessionQuery>, state: State<'_, AppState>) -> Result<Vec<database::ChatSession>, String> {
+    with_database(&state, move |database| {
+        database.query_sessions(&query.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn count_chat_sessions(query: Option<database::SessionQuery>, state: State<'_, AppState>) -> Result<usize, String> {
+    with_database(&state, move |database| {
+        database.count_sessions(&query.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// セッションを分岐したときに送るイベントのペイロード
+#[derive(Clone, serde::Serialize)]
+struct SessionForked {
+    #[serde(rename = "sessionId")]
+    session_id: String,
+    #[serde(rename = "sourceSessionId")]
+    source_session_id: String,
+}
+
+// 指定したメッセージまでをコピーした新しいセッションを作成する（元のセッションは変更しない）
+#[tauri::command]
+async fn fork_session(
+    session_id: String,
+    message_id: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+    let source_session_id = session_id.clone();
+    let forked = with_database(&state, move |database| {
+        database.fork_session(&session_id, &message_id)
+    }).await?;
     
-    database.get_sessions()
-        .map_err(|e| e.to_string())
+    let _ = app_handle.emit("session-forked", SessionForked {
+        session_id: forked.clone(),
+        source_session_id,
+    });
+    
+    Ok(forked)
 }
 
+// 指定したメッセージ以降を新しいセッションに移し、そのIDを返す
 #[tauri::command]
-fn get_chat_messages(session_id: String, state: State<'_, AppState>) -> Result<Vec<database::Message>, String> {
-    let database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_ref().ok_or("Database not initialized")?;
+async fn split_session(
+    session_id: String,
+    message_id: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+    let split = with_database(&state, move |database| {
+        database.split_session(&session_id, &message_id)
+    }).await?;
     
-    database.get_messages(&session_id)
-        .map_err(|e| e.to_string())
+    let _ = app_handle.emit("sessions-changed", ());
+    
+    Ok(split)
 }
 
+// 複数のセッションのメッセージを1つのセッションにまとめる
 #[tauri::command]
-fn add_chat_message(session_id: String, role: String, content: String, state: State<'_, AppState>) -> Result<String, String> {
-    let database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_ref().ok_or("Database not initialized")?;
+async fn merge_sessions(
+    target_session_id: String,
+    source_session_ids: Vec<String>,
+    mode: Option<database::SessionMergeMode>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.merge_sessions(&target_session_id, &source_session_ids, mode.unwrap_or_default())
+    }).await?;
     
-    database.add_message(&session_id, &role, &content)
-        .map_err(|e| e.to_string())
+    let _ = app_handle.emit("sessions-changed", ());
+    
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_session_pinned(session_id: String, pinned: bool, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.set_session_pinned(&session_id, pinned)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn set_session_archived(session_id: String, archived: bool, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.set_session_archived(&session_id, archived)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn move_session_to_folder(session_id: String, folder_id: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.move_session_to_folder(&session_id, folder_id.as_deref())
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn set_session_tags(session_id: String, tags: Vec<String>, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.set_session_tags(&session_id, &tags)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn get_folders(state: State<'_, AppState>) -> Result<Vec<database::Folder>, String> {
+    with_database(&state, move |database| {
+        database.get_folders()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn create_folder(name: String, parent_id: Option<String>, state: State<'_, AppState>) -> Result<String, String> {
+    with_database(&state, move |database| {
+        database.create_folder(&name, parent_id.as_deref())
+            .map_err(|e| e.to_string())
+    }).await
 }
 
 #[tauri::command]
-fn delete_chat_session(session_id: String, state: State<'_, AppState>) -> Result<(), String> {
+async fn rename_folder(folder_id: String, name: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.rename_folder(&folder_id, &name)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn move_folder(folder_id: String, parent_id: Option<String>, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.move_folder(&folder_id, parent_id.as_deref())
+    }).await
+}
+
+#[tauri::command]
+async fn delete_folder(folder_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.delete_folder(&folder_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn get_tags(state: State<'_, AppState>) -> Result<Vec<database::Tag>, String> {
+    with_database(&state, move |database| {
+        database.get_tags()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn delete_tag(tag_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.delete_tag(&tag_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn get_chat_messages(
+    session_id: String,
+    page: Option<database::MessagePage>,
+    state: State<'_, AppState>,
+) -> Result<Vec<database::Message>, String> {
+    with_database(&state, move |database| {
+        database.get_messages_page(&session_id, &page.unwrap_or_default())
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn count_chat_messages(session_id: String, state: State<'_, AppState>) -> Result<usize, String> {
+    with_database(&state, move |database| {
+        database.count_messages(&session_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn add_chat_message(session_id: String, role: String, content: String, state: State<'_, AppState>) -> Result<String, String> {
+    with_database(&state, move |database| {
+        database.add_message(&session_id, &role, &content)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn delete_chat_session(session_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.delete_session(&session_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn delete_chat_message(message_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.delete_message(&message_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn get_trash(state: State<'_, AppState>) -> Result<database::Trash, String> {
+    with_database(&state, move |database| {
+        database.get_trash()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn restore_chat_session(session_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.restore_session(&session_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn restore_chat_message(message_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.restore_message(&message_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn purge_chat_session(session_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.purge_session(&session_id)
+    }).await
+}
+
+#[tauri::command]
+async fn purge_chat_message(message_id: String, state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.purge_message(&message_id)
+    }).await
+}
+
+#[tauri::command]
+async fn empty_trash(state: State<'_, AppState>) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.purge_trash(None)
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// データベースを開いた直後に行う処理
+fn prepare_database(database: &Database, config: &Config) {
+    // 前回の終了時に応答待ちのまま残ったメッセージを復旧
+    if let Err(e) = database.recover_pending_messages() {
+        eprintln!("Failed to recover pending messages: {}", e);
+    }
+    // 外部キーを有効にする前に作られた不整合を修復
+    match database.repair_orphans() {
+        Ok(report) if report.total() > 0 => eprintln!("Repaired database inconsistencies: {:?}", report),
+        Ok(_) => {}
+        Err(e) => eprintln!("Failed to repair database: {}", e),
+    }
+    // 保持ポリシーを適用
+    if let Err(e) = apply_retention(database, config) {
+        eprintln!("Failed to apply retention policy: {}", e);
+    }
+    // 保存期間を過ぎたゴミ箱の項目を完全に削除
+    if config.trash_retention_days > 0 {
+        if let Err(e) = database.purge_trash(Some(config.trash_retention_days)) {
+            eprintln!("Failed to purge trash: {}", e);
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct DatabaseStatus {
+    encrypted: bool,
+    // 暗号化されたデータベースがまだ開かれていない
+    locked: bool,
+    // ファイルを開けなかったため、一時的なデータベースで動作している
+    #[serde(rename = "inMemory")]
+    in_memory: bool,
+}
+
+#[tauri::command]
+fn get_database_status(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<DatabaseStatus, String> {
+    let database = state.database.lock().unwrap().clone();
+    
+    match database {
+        Some(database) => Ok(DatabaseStatus {
+            encrypted: database.is_encrypted(),
+            locked: false,
+            in_memory: database.is_in_memory(),
+        }),
+        None => {
+            let encrypted = Database::is_encrypted_file(&app_handle)
+                .map_err(|e| e.to_string())?;
+            Ok(DatabaseStatus { encrypted, locked: encrypted, in_memory: false })
+        }
+    }
+}
+
+// パスフレーズを指定してデータベースを開く（間違っている場合はわかりやすいエラーにする）
+fn open_with_passphrase(app_handle: &tauri::AppHandle, passphrase: &str) -> Result<Database, String> {
+    Database::open(app_handle, Some(passphrase)).map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::NotADatabase => {
+            "パスフレーズが正しくありません".to_string()
+        }
+        e => format!("Failed to open database: {}", e),
+    })
+}
+
+// データベースを置き換える操作に失敗したときに、元のデータベースを開き直す
+// 開き直せなかった場合は両方のエラーを返し、診断情報にも記録して画面に知らせる
+fn reopen_previous_database(
+    app_handle: &tauri::AppHandle,
+    database_guard: &mut Option<Arc<Database>>,
+    passphrase: Option<&str>,
+    error: String,
+) -> String {
+    match Database::open(app_handle, passphrase) {
+        Ok(database) => {
+            *database_guard = Some(Arc::new(database));
+            error
+        }
+        Err(reopen_error) => {
+            *database_guard = None;
+            let message = format!("{}; reopening the previous database also failed: {}", error, reopen_error);
+            let state = app_handle.state::<AppState>();
+            let mut diagnostics = state.diagnostics.lock().unwrap();
+            diagnostics.database_error = Some(message.clone());
+            let _ = app_handle.emit("startup-diagnostics", &*diagnostics);
+            message
+        }
+    }
+}
+
+#[tauri::command]
+async fn unlock_database(passphrase: String, state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<(), String> {
+    if state.database.lock().unwrap().is_some() {
+        return Ok(());
+    }
+    
+    // 鍵の導出に時間がかかるため、コマンドのスレッドでは開かない
+    let config = state.config.lock().unwrap().clone();
+    let database = run_blocking(move || {
+        let database = open_with_passphrase(&app_handle, &passphrase)?;
+        prepare_database(&database, &config);
+        Ok(database)
+    }).await?;
+    
+    state.database.lock().unwrap().get_or_insert_with(|| Arc::new(database));
+    state.record_activity();
+    
+    Ok(())
+}
+
+// 暗号化されたデータベースを閉じる（暗号化されていない場合は何もしない）
+// 実行中の処理があれば、その処理が終わった時点で接続が閉じられる
+fn lock(state: &AppState) -> bool {
     let mut database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_mut().ok_or("Database not initialized")?;
+    if database_guard.as_ref().is_some_and(|database| database.is_encrypted()) {
+        *database_guard = None;
+        return true;
+    }
+    false
+}
+
+#[tauri::command]
+fn lock_database(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<(), String> {
+    if lock(&state) {
+        let _ = app_handle.emit("database-locked", ());
+    }
     
-    database.delete_session(&session_id)
-        .map_err(|e| e.to_string())
+    Ok(())
+}
+
+// 操作がないまま設定した時間が過ぎたら、暗号化されたデータベースをロックする
+fn spawn_auto_lock(app_handle: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(30));
+        
+        let state = app_handle.state::<AppState>();
+        let minutes = match state.config.lock().unwrap().auto_lock_minutes {
+            Some(minutes) if minutes > 0 => minutes,
+            _ => continue,
+        };
+        if state.last_activity.lock().unwrap().elapsed() < Duration::from_secs(minutes as u64 * 60) {
+            continue;
+        }
+        
+        if lock(&state) {
+            let _ = app_handle.emit("database-locked", ());
+        }
+    });
+}
+
+#[tauri::command]
+fn record_activity(state: State<'_, AppState>) {
+    state.record_activity();
+}
+
+// パスフレーズを変更して開き直す。失敗した場合は元のパスフレーズで開き直す
+// current_passphraseを指定した場合は、変更前にそのパスフレーズが正しいことを確認する
+async fn replace_passphrase(
+    app_handle: tauri::AppHandle,
+    current_passphrase: Option<String>,
+    passphrase: Option<String>,
+) -> Result<(), String> {
+    run_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        {
+            let database = state.database()?;
+            ensure_file_database(&database)?;
+            // 開いているデータベースのパスフレーズと照合する（データベースをもう一度開き直さない）
+            if let Some(current) = &current_passphrase {
+                if database.passphrase() != Some(current.as_str()) {
+                    return Err("パスフレーズが正しくありません".to_string());
+                }
+            }
+        }
+        
+        let database = take_database(&state)?;
+        let current = database.passphrase().map(|p| p.to_string());
+        
+        let result = database.change_passphrase(&app_handle, passphrase.as_deref());
+        let mut database_guard = state.database.lock().unwrap();
+        match result {
+            Ok(database) => {
+                *database_guard = Some(Arc::new(database));
+                Ok(())
+            }
+            Err(e) => Err(reopen_previous_database(
+                &app_handle,
+                &mut database_guard,
+                current.as_deref(),
+                format!("Failed to change passphrase: {}", e),
+            )),
+        }
+    }).await
+}
+
+fn is_database_encrypted(state: &AppState) -> Result<bool, String> {
+    Ok(state.database()?.is_encrypted())
+}
+
+#[tauri::command]
+async fn enable_database_encryption(passphrase: String, state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<(), String> {
+    if passphrase.is_empty() {
+        return Err("パスフレーズを入力してください".to_string());
+    }
+    if is_database_encrypted(&state)? {
+        return Err("データベースはすでに暗号化されています".to_string());
+    }
+    
+    replace_passphrase(app_handle, None, Some(passphrase)).await
+}
+
+#[tauri::command]
+async fn disable_database_encryption(current_passphrase: String, state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<(), String> {
+    if !is_database_encrypted(&state)? {
+        return Err("データベースは暗号化されていません".to_string());
+    }
+    
+    replace_passphrase(app_handle, Some(current_passphrase), None).await
+}
+
+#[tauri::command]
+async fn change_database_passphrase(
+    current_passphrase: String,
+    new_passphrase: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if new_passphrase.is_empty() {
+        return Err("パスフレーズを入力してください".to_string());
+    }
+    if !is_database_encrypted(&state)? {
+        return Err("データベースは暗号化されていません".to_string());
+    }
+    
+    replace_passphrase(app_handle, Some(current_passphrase), Some(new_passphrase)).await
+}
+
+#[tauri::command]
+async fn create_backup(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<BackupInfo, String> {
+    let config = state.config.lock().unwrap().clone();
+    
+    with_database(&state, move |database| {
+        ensure_file_database(database)?;
+        backup::create_backup(&app_handle, database, &config, true)
+    }).await
+}
+
+#[tauri::command]
+fn list_backups(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<Vec<BackupInfo>, String> {
+    let config = state.config.lock().unwrap().clone();
+    backup::list_backups(&app_handle, &config)
+}
+
+// バックアップから復元する
+// passphraseを省略した場合、暗号化されたバックアップは現在のパスフレーズで開く
+#[tauri::command]
+async fn restore_backup(
+    name: String,
+    passphrase: Option<String>,
+    restore_config: bool,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let config = state.config.lock().unwrap().clone();
+    let path = backup::backup_path(&app_handle, &config, &name)?;
+    
+    {
+        let app_handle = app_handle.clone();
+        let config = config.clone();
+        let db_file = path.join(backup::DATABASE_FILE);
+        run_blocking(move || {
+            let state = app_handle.state::<AppState>();
+            
+            // 復元前に現在の状態をバックアップしておく
+            {
+                let database = state.database()?;
+                ensure_file_database(&database)?;
+                backup::create_backup(&app_handle, &database, &config, false)?;
+            }
+            
+            let database = take_database(&state)?;
+            let previous_passphrase = database.passphrase().map(|p| p.to_string());
+            let passphrase = if Database::is_encrypted_path(&db_file).map_err(|e| e.to_string())? {
+                passphrase.or_else(|| previous_passphrase.clone())
+            } else {
+                None
+            };
+            
+            let result = database.restore_from(&app_handle, &db_file, passphrase.as_deref());
+            let mut database_guard = state.database.lock().unwrap();
+            match result {
+                Ok(database) => {
+                    prepare_database(&database, &config);
+                    *database_guard = Some(Arc::new(database));
+                    Ok(())
+                }
+                Err(e) => Err(reopen_previous_database(
+                    &app_handle,
+                    &mut database_guard,
+                    previous_passphrase.as_deref(),
+                    format!("restore failed: {}", e),
+                )),
+            }
+        }).await?;
+    }
+    
+    if restore_config {
+        if let Some(restored) = backup::read_config(&path)? {
+            save_config(&app_handle, &restored)?;
+            *state.config.lock().unwrap() = restored;
+        }
+    }
+    
+    Ok(())
+}
+
+// 設定した間隔でバックアップを作成する
+fn spawn_backup_scheduler(app_handle: tauri::AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(10 * 60));
+        
+        let state = app_handle.state::<AppState>();
+        let config = state.config.lock().unwrap().clone();
+        match backup::is_backup_due(&app_handle, &config) {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                eprintln!("Failed to check backups: {}", e);
+                continue;
+            }
+        }
+        
+        // ロック中と一時的なデータベースで動作している間はバックアップしない
+        let database = state.database.lock().unwrap().clone();
+        if let Some(database) = database.filter(|database| !database.is_in_memory()) {
+            if let Err(e) = backup::create_backup(&app_handle, &database, &config, true) {
+                eprintln!("Failed to create backup: {}", e);
+            }
+        }
+    });
+}
+
+#[tauri::command]
+async fn database_stats(state: State<'_, AppState>) -> Result<database::DatabaseStats, String> {
+    with_database(&state, move |database| {
+        database.stats()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn run_database_maintenance(state: State<'_, AppState>) -> Result<database::MaintenanceReport, String> {
+    with_database(&state, move |database| {
+        database.run_maintenance()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn repair_database(state: State<'_, AppState>) -> Result<database::RepairReport, String> {
+    with_database(&state, move |database| {
+        database.repair_orphans()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// 埋め込みがまだないメッセージを少しずつ処理し、計算したメッセージ数を返す
+async fn index_embeddings(app_handle: &tauri::AppHandle) -> Result<usize, String> {
+    let state = app_handle.state::<AppState>();
+    let config = state.config.lock().unwrap().clone();
+    let embedder = match embeddings::from_config(&config) {
+        Some(embedder) => embedder,
+        None => return Ok(0),
+    };
+    let model = embedder.model().to_string();
+    let mut indexed = 0;
+    
+    loop {
+        let pending = {
+            let model = model.clone();
+            with_database_in_background(&state, move |database| {
+                database.pending_embeddings(&model, embeddings::BATCH_SIZE)
+                    .map_err(|e| e.to_string())
+            }).await?
+        };
+        if pending.is_empty() {
+            return Ok(indexed);
+        }
+        
+        let (ids, texts): (Vec<String>, Vec<String>) = pending.into_iter().unzip();
+        let mut batch = Vec::with_capacity(ids.len());
+        let mut failures = Vec::new();
+        match embedder.embed(&texts).await {
+            Ok(vectors) => batch.extend(ids.into_iter().zip(vectors.into_iter().map(embeddings::normalize))),
+            // どのメッセージが拒否されたか分からないので1件ずつ計算し直し、拒否されたメッセージは記録して飛ばす
+            Err(EmbedError::Rejected(_)) => {
+                for (id, text) in ids.into_iter().zip(texts) {
+                    match embedder.embed(std::slice::from_ref(&text)).await {
+                        Ok(vectors) => batch.extend(vectors.into_iter().map(embeddings::normalize).map(|vector| (id.clone(), vector))),
+                        Err(EmbedError::Rejected(e)) => failures.push((id, e)),
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            // APIを使えない間は、次回にまとめて計算し直す
+            Err(e) => return Err(e.into()),
+        }
+        indexed += batch.len();
+        
+        let model = model.clone();
+        with_database_in_background(&state, move |database| {
+            database.save_embeddings(&model, &batch)
+                .and_then(|_| database.record_embedding_failures(&model, &failures))
+                .map_err(|e| e.to_string())
+        }).await?;
+    }
+}
+
+// 新しいメッセージの埋め込みをバックグラウンドで計算する
+fn spawn_embedding_indexer(app_handle: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            
+            // ロック中は何もしない
+            if app_handle.state::<AppState>().database.lock().unwrap().is_none() {
+                continue;
+            }
+            if let Err(e) = index_embeddings(&app_handle).await {
+                eprintln!("Failed to index embeddings: {}", e);
+            }
+        }
+    });
+}
+
+// 意味の近いメッセージを検索する
+#[tauri::command]
+async fn semantic_search(
+    query: String,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Vec<database::SemanticSearchResult>, String> {
+    let config = state.config.lock().unwrap().clone();
+    let embedder = embeddings::from_config(&config)
+        .ok_or("セマンティック検索が有効になっていません")?;
+    
+    let vector = embedder.embed(std::slice::from_ref(&query)).await?
+        .pop()
+        .map(embeddings::normalize)
+        .ok_or("埋め込みAPIの応答がありません")?;
+    let model = embedder.model().to_string();
+    
+    with_database(&state, move |database| {
+        database.semantic_search(&model, &vector, limit.unwrap_or(20).max(1), None)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// セッション内の応答が参考にした過去のメッセージ（応答のIDごと）
+#[tauri::command]
+async fn get_session_citations(
+    session_id: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, Vec<database::Citation>>, String> {
+    with_database(&state, move |database| {
+        database.get_session_citations(&session_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn set_message_bookmarked(
+    message_id: String,
+    bookmarked: bool,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.set_message_bookmarked(&message_id, bookmarked)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// メッセージのメモを保存する（空にすると削除）
+#[tauri::command]
+async fn set_message_note(
+    message_id: String,
+    note: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.set_message_note(&message_id, note.as_deref())
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// セッション内のメッセージのスターとメモ（メッセージのIDごと）
+#[tauri::command]
+async fn get_session_annotations(
+    session_id: String,
+    state: State<'_, AppState>,
+) -> Result<HashMap<String, database::MessageAnnotation>, String> {
+    with_database(&state, move |database| {
+        database.get_session_annotations(&session_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+// すべてのセッションからスターを付けたメッセージを返す
+#[tauri::command]
+async fn get_bookmarks(state: State<'_, AppState>) -> Result<Vec<database::Bookmark>, String> {
+    with_database(&state, move |database| {
+        database.get_bookmarks()
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn get_knowledge_collections(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<Vec<database::KnowledgeCollection>, String> {
+    with_database(&state, move |database| {
+        let mut collections = database.get_collections()
+            .map_err(|e| e.to_string())?;
+        
+        // 資料とチャンクの数はコレクションごとの保存先から数える（まだ取り込んでいなければ0）
+        for collection in &mut collections {
+            let path = knowledge_store::store_path(&app_handle, &collection.id)?;
+            if path.exists() {
+                (collection.document_count, collection.chunk_count) = knowledge_store::KnowledgeStore::open(&path)
+                    .and_then(|store| store.counts())
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        
+        Ok(collections)
+    }).await
+}
+
+#[tauri::command]
+async fn create_knowledge_collection(
+    name: String,
+    folder: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("コレクションの名前を入力してください".to_string());
+    }
+    if !std::path::Path::new(&folder).is_dir() {
+        return Err(format!("フォルダが見つかりません: {}", folder));
+    }
+    
+    with_database(&state, move |database| {
+        database.create_collection(&name, &folder)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn delete_knowledge_collection(collection_id: String, state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.delete_collection(&collection_id)
+            .map_err(|e| e.to_string())?;
+        knowledge_store::delete(&app_handle, &collection_id)
+    }).await
+}
+
+// コレクションのフォルダから資料を取り込む
+// 取り込み済みの資料はハッシュを比べ、変更されたものだけを取り込み直す
+#[tauri::command]
+async fn ingest_knowledge_collection(
+    collection_id: String,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<knowledge::IngestReport, String> {
+    let config = state.config.lock().unwrap().clone();
+    let embedder = embeddings::from_config(&config)
+        .ok_or("資料を取り込むには、設定でセマンティック検索を有効にしてください")?;
+    let model = embedder.model().to_string();
+    
+    let folder = {
+        let collection_id = collection_id.clone();
+        with_database(&state, move |database| {
+            database.get_collection_folder(&collection_id)
+                .map(PathBuf::from)
+                .map_err(|e| e.to_string())
+        }).await?
+    };
+    let store_path = knowledge_store::store_path(&app_handle, &collection_id)?;
+    let documents = {
+        let store_path = store_path.clone();
+        let model = model.clone();
+        run_blocking(move || {
+            knowledge_store::KnowledgeStore::open(&store_path)
+                .and_then(|store| store.get_documents(&model))
+                .map_err(|e| e.to_string())
+        }).await?
+    };
+    let mut documents: HashMap<String, database::KnowledgeDocument> = documents.into_iter()
+        .map(|document| (document.path.clone(), document))
+        .collect();
+    
+    let (files, scan_failures) = {
+        let folder = folder.clone();
+        run_blocking(move || knowledge::scan(&folder)).await?
+    };
+    let total = files.len();
+    let mut report = knowledge::IngestReport::default();
+    // 読み込めなかったフォルダの資料は、無くなったものとして削除しない
+    documents.retain(|path, _| !scan_failures.iter().any(|failure| knowledge::is_under(path, &failure.path)));
+    report.failed = scan_failures;
+    
+    for (index, file) in files.into_iter().enumerate() {
+        let path = knowledge::relative_path(&folder, &file);
+        let existing = documents.remove(&path);
+        
+        // 内容が変わっていない資料は読み飛ばす
+        let extracted = run_blocking(move || {
+            let (bytes, hash) = knowledge::read(&file)?;
+            if existing.as_ref().is_some_and(|document| document.hash == hash && document.up_to_date) {
+                return Ok(None);
+            }
+            let text = knowledge::extract_text(&file, &bytes)?;
+            Ok(Some((hash, knowledge::chunk(&text), existing.is_some())))
+        }).await;
+        
+        match extracted {
+            Ok(None) => report.unchanged += 1,
+            Ok(Some((hash, chunks, is_update))) => {
+                // 埋め込みを計算できなかった資料は失敗として記録し、残りの資料の取り込みを続ける
+                let mut embedded = Vec::with_capacity(chunks.len());
+                let mut embed_error = None;
+                for batch in chunks.chunks(embeddings::BATCH_SIZE) {
+                    match embedder.embed(batch).await {
+                        Ok(vectors) => embedded.extend(batch.iter().cloned().zip(vectors.into_iter().map(embeddings::normalize))),
+                        Err(e) => {
+                            embed_error = Some(e.to_string());
+                            break;
+                        }
+                    }
+                }
+                
+                if let Some(error) = embed_error {
+                    report.failed.push(knowledge::IngestFailure { path, error });
+                } else {
+                    report.chunks += embedded.len();
+                    
+                    let store_path = store_path.clone();
+                    let model = model.clone();
+                    let document_path = path.clone();
+                    run_blocking(move || {
+                        knowledge_store::KnowledgeStore::open(&store_path)
+                            .and_then(|mut store| store.save_document(&document_path, &hash, &model, &embedded))
+                            .map_err(|e| e.to_string())
+                    }).await?;
+                    
+                    if is_update {
+                        report.updated += 1;
+                    } else {
+                        report.added += 1;
+                    }
+                }
+            }
+            Err(error) => report.failed.push(knowledge::IngestFailure { path, error }),
+        }
+        
+        let _ = app_handle.emit("knowledge-ingest-progress", knowledge::IngestProgress {
+            collection_id: collection_id.clone(),
+            processed: index + 1,
+            total,
+        });
+    }
+    
+    // フォルダから無くなった資料を削除する
+    let removed: Vec<String> = documents.into_keys().collect();
+    report.removed = removed.len();
+    if !removed.is_empty() {
+        run_blocking(move || {
+            knowledge_store::KnowledgeStore::open(&store_path)
+                .and_then(|mut store| store.remove_documents(&removed))
+                .map_err(|e| e.to_string())
+        }).await?;
+    }
+    
+    with_database(&state, move |database| {
+        database.touch_collection(&collection_id)
+            .map_err(|e| e.to_string())
+    }).await?;
+    
+    Ok(report)
+}
+
+#[tauri::command]
+async fn get_session_collections(session_id: String, state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    with_database(&state, move |database| {
+        database.get_session_collections(&session_id)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn set_session_collections(
+    session_id: String,
+    collection_ids: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    with_database(&state, move |database| {
+        database.set_session_collections(&session_id, &collection_ids)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[tauri::command]
+async fn get_embedding_status(state: State<'_, AppState>) -> Result<database::EmbeddingStatus, String> {
+    let model = state.config.lock().unwrap().embedding_model.clone();
+    
+    with_database(&state, move |database| {
+        database.embedding_status(&model)
+            .map_err(|e| e.to_string())
+    }).await
+}
+
+#[derive(serde::Serialize, Clone, Default)]
+struct StartupDiagnostics {
+    // 設定を読み込めなかったため、デフォルト設定で動作している
+    #[serde(rename = "configError")]
+    config_error: Option<String>,
+    // 読み込めなかった設定ファイルの移動先
+    #[serde(rename = "configPreservedPath")]
+    config_preserved_path: Option<String>,
+    #[serde(rename = "databaseError")]
+    database_error: Option<String>,
+    // データベースを開けなかったため、終了すると消える一時的なデータベースで動作している
+    #[serde(rename = "inMemory")]
+    in_memory: bool,
+}
+
+impl StartupDiagnostics {
+    fn has_problems(&self) -> bool {
+        self.config_error.is_some() || self.database_error.is_some()
+    }
+}
+
+// 使用中のプロファイルの設定を読み込み、データベースを開く
+// 暗号化されている場合はunlock_databaseで開くまでロックしておく
+// 設定やデータベースを読み込めなくても起動は続け、問題はstartup-diagnosticsイベントで画面に知らせる
+fn load_profile(app_handle: &tauri::AppHandle) {
+    let state = app_handle.state::<AppState>();
+    let mut diagnostics = StartupDiagnostics::default();
+    
+    let config = load_config(app_handle).unwrap_or_else(|e| {
+        eprintln!("Failed to load config: {}", e);
+        diagnostics.config_error = Some(e.to_string());
+        if let ConfigError::Parse { preserved_path, .. } = &e {
+            eprintln!("Unreadable config was moved to {}", preserved_path.display());
+            diagnostics.config_preserved_path = Some(preserved_path.display().to_string());
+        }
+        Config::default()
+    });
+    *state.config.lock().unwrap() = config.clone();
+    // APIキーはプロファイルごとに異なるので、クライアントは画面から初期化し直す
+    *state.mcp_client.lock().unwrap() = None;
+    state.record_activity();
+    
+    let opened = match Database::is_encrypted_file(app_handle) {
+        Ok(true) => Ok(None),
+        Ok(false) => Database::new(app_handle).map(Some),
+        Err(e) => Err(e),
+    };
+    let database = match opened {
+        Ok(database) => database,
+        Err(e) => {
+            eprintln!("Failed to initialize database: {}", e);
+            diagnostics.database_error = Some(e.to_string());
+            // チャットは続けられるように、メモリ上の一時的なデータベースで動作する
+            match Database::open_in_memory() {
+                Ok(database) => {
+                    diagnostics.in_memory = true;
+                    Some(database)
+                }
+                Err(e) => {
+                    eprintln!("Failed to open in-memory database: {}", e);
+                    None
+                }
+            }
+        }
+    };
+    if let Some(database) = &database {
+        prepare_database(database, &config);
+    }
+    *state.database.lock().unwrap() = database.map(Arc::new);
+    
+    if diagnostics.has_problems() {
+        let _ = app_handle.emit("startup-diagnostics", &diagnostics);
+    }
+    *state.diagnostics.lock().unwrap() = diagnostics;
+}
+
+// 起動時のイベントは画面の準備ができる前に送られることがあるので、画面からも問い合わせられるようにする
+#[tauri::command]
+fn get_startup_diagnostics(state: State<'_, AppState>) -> StartupDiagnostics {
+    state.diagnostics.lock().unwrap().clone()
+}
+
+// 一時的なデータベースでは、データベースファイルを置き換える操作やバックアップは行わない
+fn ensure_file_database(database: &Database) -> Result<(), String> {
+    if database.is_in_memory() {
+        return Err("データベースファイルを開けなかったため、一時的なデータベースで動作しています。この操作は行えません".to_string());
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct ProfileList {
+    active: String,
+    profiles: Vec<profile::Profile>,
+}
+
+#[tauri::command]
+fn get_profiles(app_handle: tauri::AppHandle) -> Result<ProfileList, String> {
+    let profiles = profile::load(&app_handle)?;
+    Ok(ProfileList {
+        active: profiles.active_profile().id,
+        profiles: profiles.profiles,
+    })
+}
+
+// プロファイルを作成する（設定はデフォルトから始まる）
+#[tauri::command]
+fn create_profile(name: String, own_database: bool, app_handle: tauri::AppHandle) -> Result<profile::Profile, String> {
+    profile::create(&app_handle, &name, own_database)
+}
+
+// プロファイルを切り替え、設定とデータベースを読み込み直す
+#[tauri::command]
+async fn switch_profile(profile_id: String, app_handle: tauri::AppHandle) -> Result<(), String> {
+    run_blocking(move || {
+        let state = app_handle.state::<AppState>();
+        
+        // 実行中の処理が終わるのを待ってからデータベースを閉じる（ロック中の場合は開いていない）
+        if state.database.lock().unwrap().is_some() {
+            drop(take_database(&state)?);
+        }
+        
+        // 切り替えに失敗した場合も、元のプロファイルを読み込み直して使える状態に戻す
+        let result = profile::set_active(&app_handle, &profile_id);
+        load_profile(&app_handle);
+        result?;
+        
+        let _ = app_handle.emit("profile-switched", &profile_id);
+        Ok(())
+    }).await
+}
+
+#[tauri::command]
+fn delete_profile(profile_id: String, delete_data: bool, app_handle: tauri::AppHandle) -> Result<(), String> {
+    profile::delete(&app_handle, &profile_id, delete_data)
 }
 
 #[tauri::command]
@@ -164,18 +1520,26 @@ fn get_config(state: State<'_, AppState>) -> Result<Config, String> {
 #[tauri::command]
 fn save_config_command(config: Config, state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<(), String> {
     *state.config.lock().unwrap() = config.clone();
-    save_config(&app_handle, &config)
+    save_config(&app_handle, &config).map_err(String::from)
 }
 
 #[tauri::command]
-fn export_chat_history(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<String, String> {
+async fn export_chat_history(
+    format: Option<ExportFormat>,
+    session_ids: Option<Vec<String>>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+    let format = format.unwrap_or(ExportFormat::Json);
+    let (filter_name, extension) = format.filter();
+    
     // ファイル保存ダイアログを表示
     let file_path = std::sync::Arc::new(std::sync::Mutex::new(None));
     let file_path_clone = file_path.clone();
     
     app_handle.dialog()
         .file()
-        .add_filter("JSON", &["json"])
+        .add_filter(filter_name, &[extension])
         .save_file(move |path| {
             if let Ok(mut guard) = file_path_clone.lock() {
                 *guard = path;
@@ -183,10 +1547,10 @@ fn export_chat_history(state: State<'_, AppState>, app_handle: tauri::AppHandle)
         });
     
     // ダイアログの結果を待つための短い遅延
-    std::thread::sleep(std::time::Duration::from_millis(100));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
     
     // ファイルパスを取得
-    let path_option = file_path.lock().map_err(|e| format!("ミューテックスのロックに失敗しました: {}", e))?;
+    let path_option = file_path.lock().map_err(|e| format!("ミューテックスのロックに失敗しました: {}", e))?.take();
     
     // ファイルパスが選択されなかった場合
     if path_option.is_none() {
@@ -195,47 +1559,69 @@ fn export_chat_history(state: State<'_, AppState>, app_handle: tauri::AppHandle)
     
     let file_path = path_option.as_ref().unwrap().to_string();
     
-    // データベースからエクスポート
-    let database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_ref().ok_or("データベースが初期化されていません")?;
+    // データベースからエクスポート（読み込み用の接続を使うため、エクスポート中も他の操作は止まらない）
+    let export_path = file_path.clone();
+    let result = with_database(&state, move |database| match format {
+        ExportFormat::Json => database.export_data(&export_path, session_ids.as_deref()),
+        ExportFormat::Jsonl => database.export_jsonl(&export_path, session_ids.as_deref(), &mut |progress| {
+            let _ = app_handle.emit("export-progress", progress);
+        }),
+        ExportFormat::Markdown | ExportFormat::Html => {
+            let transcripts = database.get_transcripts(session_ids.as_deref())
+                .map_err(|e| e.to_string())?;
+            transcript::write_transcripts(&export_path, format, &transcripts)
+        }
+    }).await;
     
-    database.export_data(&file_path)
-        .map(|_| format!("チャット履歴を正常にエクスポートしました: {}", file_path))
+    result.map(|_| format!("チャット履歴を正常にエクスポートしました: {}", file_path))
 }
 #[tauri::command]
-fn import_chat_history(state: State<'_, AppState>, app_handle: tauri::AppHandle) -> Result<String, String> {
-    // ファイル選択ダイアログを表示
-    let file_path = std::sync::Arc::new(std::sync::Mutex::new(None));
-    let file_path_clone = file_path.clone();
-    
-    app_handle.dialog()
-        .file()
-        .add_filter("JSON", &["json"])
-        .pick_file(move |path| {
-            if let Ok(mut guard) = file_path_clone.lock() {
-                *guard = path;
+async fn import_chat_history(
+    file_path: Option<String>,
+    source: Option<ImportSource>,
+    strategy: Option<MergeStrategy>,
+    dry_run: Option<bool>,
+    state: State<'_, AppState>,
+    app_handle: tauri::AppHandle,
+) -> Result<ImportReport, String> {
+    // ドライランの結果を確認した後など、パスが指定されている場合はダイアログを表示しない
+    let file_path = match file_path {
+        Some(file_path) => file_path,
+        None => {
+            // ファイル選択ダイアログを表示
+            let file_path = std::sync::Arc::new(std::sync::Mutex::new(None));
+            let file_path_clone = file_path.clone();
+            
+            app_handle.dialog()
+                .file()
+                .add_filter("JSON / エクスポートアーカイブ", &["json", "jsonl", "zip"])
+                .pick_file(move |path| {
+                    if let Ok(mut guard) = file_path_clone.lock() {
+                        *guard = path;
+                    }
+                });
+            
+            // ダイアログの結果を待つための短い遅延
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            
+            // ファイルパスを取得
+            let path_option = file_path.lock().map_err(|e| format!("ミューテックスのロックに失敗しました: {}", e))?.take();
+            
+            // ファイルパスが選択されなかった場合
+            if path_option.is_none() {
+                return Err("インポートがキャンセルされました。".to_string());
             }
-        });
-    
-    // ダイアログの結果を待つための短い遅延
-    std::thread::sleep(std::time::Duration::from_millis(100));
-    
-    // ファイルパスを取得
-    let path_option = file_path.lock().map_err(|e| format!("ミューテックスのロックに失敗しました: {}", e))?;
-    
-    // ファイルパスが選択されなかった場合
-    if path_option.is_none() {
-        return Err("インポートがキャンセルされました。".to_string());
-    }
-    
-    let file_path = path_option.as_ref().unwrap().to_string().to_string();
+            
+            path_option.as_ref().unwrap().to_string()
+        }
+    };
     
     // データベースにインポート
-    let mut database_guard = state.database.lock().unwrap();
-    let database = database_guard.as_mut().ok_or("データベースが初期化されていません")?;
-    
-    database.import_data(&file_path)
-        .map(|_| format!("チャット履歴を正常にインポートしました: {}", file_path))
+    with_database(&state, move |database| {
+        database.import_data(&file_path, source, strategy.unwrap_or_default(), dry_run.unwrap_or(false), &mut |progress| {
+            let _ = app_handle.emit("import-progress", progress);
+        })
+    }).await
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -247,24 +1633,19 @@ pub fn run() {
             mcp_client: Mutex::new(None),
             database: Mutex::new(None),
             config: Mutex::new(Config::default()),
+            last_activity: Mutex::new(Instant::now()),
+            diagnostics: Mutex::new(StartupDiagnostics::default()),
         })
         .setup(|app| {
             // アプリケーション初期化処理
             let app_handle = app.handle();
             
-            // 設定の読み込み
-            let config = load_config(&app_handle);
-            *app.state::<AppState>().config.lock().unwrap() = config;
+            // 使用中のプロファイルの設定とデータベースを読み込む
+            load_profile(app_handle);
             
-            // データベースの初期化
-            match Database::new(&app_handle) {
-                Ok(database) => {
-                    *app.state::<AppState>().database.lock().unwrap() = Some(database);
-                }
-                Err(e) => {
-                    eprintln!("Failed to initialize database: {}", e);
-                }
-            }
+            spawn_auto_lock(app_handle.clone());
+            spawn_backup_scheduler(app_handle.clone());
+            spawn_embedding_indexer(app_handle.clone());
             
             Ok(())
         })
@@ -273,12 +1654,67 @@ pub fn run() {
             initialize_mcp,
             send_message,
             create_chat_session,
+            rename_chat_session,
             get_chat_sessions,
+            count_chat_sessions,
+            fork_session,
+            split_session,
+            merge_sessions,
+            set_session_pinned,
+            set_session_archived,
+            move_session_to_folder,
+            set_session_tags,
+            get_folders,
+            create_folder,
+            rename_folder,
+            move_folder,
+            delete_folder,
+            get_tags,
+            delete_tag,
             get_chat_messages,
+            count_chat_messages,
             add_chat_message,
             delete_chat_session,
+            delete_chat_message,
+            get_trash,
+            restore_chat_session,
+            restore_chat_message,
+            purge_chat_session,
+            purge_chat_message,
+            empty_trash,
+            get_startup_diagnostics,
+            get_profiles,
+            create_profile,
+            switch_profile,
+            delete_profile,
             get_config,
             save_config_command,
+            get_database_status,
+            unlock_database,
+            lock_database,
+            enable_database_encryption,
+            disable_database_encryption,
+            change_database_passphrase,
+            record_activity,
+            create_backup,
+            list_backups,
+            restore_backup,
+            database_stats,
+            run_database_maintenance,
+            repair_database,
+            semantic_search,
+            get_embedding_status,
+            get_session_citations,
+            set_message_bookmarked,
+            set_message_note,
+            get_session_annotations,
+            get_bookmarks,
+            get_knowledge_collections,
+            create_knowledge_collection,
+            delete_knowledge_collection,
+            ingest_knowledge_collection,
+            get_session_collections,
+            set_session_collections,
             export_chat_history,
             import_chat_history,
         ])
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tauri::Manager;
+use uuid::Uuid;
+
+// 既定のプロファイル（プロファイルを作る前からある設定とデータベースをそのまま使う）
+pub const DEFAULT_PROFILE_ID: &str = "default";
+const DEFAULT_PROFILE_NAME: &str = "デフォルト";
+const PROFILES_FILE: &str = "profiles.json";
+// 既定以外のプロファイルの設定とデータベースはこのディレクトリの下のプロファイルIDのディレクトリに置く
+const PROFILES_DIR: &str = "profiles";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+    // プロファイル専用のデータベースを使う（falseの場合は既定のデータベースを共有する）
+    #[serde(rename = "ownDatabase")]
+    pub own_database: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Profiles {
+    // 使用中のプロファイルのID
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_PROFILE_ID.to_string(),
+            profiles: vec![Profile {
+                id: DEFAULT_PROFILE_ID.to_string(),
+                name: DEFAULT_PROFILE_NAME.to_string(),
+                own_database: false,
+            }],
+        }
+    }
+}
+
+impl Profiles {
+    fn find(&self, profile_id: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.id == profile_id)
+    }
+
+    // 使用中のプロファイル（一覧に無い場合は既定のプロファイル）
+    pub fn active_profile(&self) -> Profile {
+        self.find(&self.active)
+            .or_else(|| self.find(DEFAULT_PROFILE_ID))
+            .cloned()
+            .unwrap_or_else(|| Profiles::default().profiles.remove(0))
+    }
+}
+
+fn config_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app_handle.path().app_config_dir()
+        .map_err(|e| format!("Failed to get app config dir: {}", e))
+}
+
+fn profiles_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    Ok(config_dir(app_handle)?.join(PROFILES_FILE))
+}
+
+// プロファイルの一覧を読み込む（まだ無い場合は既定のプロファイルだけ）
+// ファイルを読み込めない場合はエラーを返す。作成や切り替えで既定の一覧が上書き保存されないよう、呼び出し側はそこで中断する
+pub fn load(app_handle: &tauri::AppHandle) -> Result<Profiles, String> {
+    let path = profiles_path(app_handle)?;
+    if !path.exists() {
+        return Ok(Profiles::default());
+    }
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read profiles file {}: {}", path.display(), e))?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse profiles file {}: {}", path.display(), e))
+}
+
+fn save(app_handle: &tauri::AppHandle, profiles: &Profiles) -> Result<(), String> {
+    let dir = config_dir(app_handle)?;
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create config directory: {}", e))?;
+
+    let json = serde_json::to_string_pretty(profiles)
+        .map_err(|e| format!("Failed to serialize profiles: {}", e))?;
+    fs::write(dir.join(PROFILES_FILE), json)
+        .map_err(|e| format!("Failed to write profiles file: {}", e))
+}
+
+pub fn active(app_handle: &tauri::AppHandle) -> Result<Profile, String> {
+    Ok(load(app_handle)?.active_profile())
+}
+
+// プロファイルの設定ファイルのディレクトリ
+pub fn config_dir_of(app_handle: &tauri::AppHandle, profile: &Profile) -> Result<PathBuf, String> {
+    let dir = config_dir(app_handle)?;
+    if profile.id == DEFAULT_PROFILE_ID {
+        Ok(dir)
+    } else {
+        Ok(dir.join(PROFILES_DIR).join(&profile.id))
+    }
+}
+
+// プロファイルのデータベースとバックアップを置くディレクトリ
+pub fn data_dir_of(app_handle: &tauri::AppHandle, profile: &Profile) -> Result<PathBuf, String> {
+    let dir = app_handle.path().app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    if profile.own_database && profile.id != DEFAULT_PROFILE_ID {
+        Ok(dir.join(PROFILES_DIR).join(&profile.id))
+    } else {
+        Ok(dir)
+    }
+}
+
+pub fn create(app_handle: &tauri::AppHandle, name: &str, own_database: bool) -> Result<Profile, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("プロファイルの名前を入力してください".to_string());
+    }
+
+    let mut profiles = load(app_handle)?;
+    if profiles.profiles.iter().any(|profile| profile.name == name) {
+        return Err(format!("同じ名前のプロファイルがすでにあります: {}", name));
+    }
+
+    let profile = Profile {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        own_database,
+    };
+    profiles.profiles.push(profile.clone());
+    save(app_handle, &profiles)?;
+
+    Ok(profile)
+}
+
+pub fn set_active(app_handle: &tauri::AppHandle, profile_id: &str) -> Result<(), String> {
+    let mut profiles = load(app_handle)?;
+    if profiles.find(profile_id).is_none() {
+        return Err(format!("プロファイルが見つかりません: {}", profile_id));
+    }
+
+    profiles.active = profile_id.to_string();
+    save(app_handle, &profiles)
+}
+
+// プロファイルを削除する（使用中のプロファイルと既定のプロファイルは削除できない）
+// delete_dataを指定した場合は、プロファイル専用のデータベースとバックアップも削除する
+pub fn delete(app_handle: &tauri::AppHandle, profile_id: &str, delete_data: bool) -> Result<(), String> {
+    let mut profiles = load(app_handle)?;
+    if profile_id == DEFAULT_PROFILE_ID {
+        return Err("デフォルトのプロファイルは削除できません".to_string());
+    }
+    if profile_id == profiles.active {
+        return Err("使用中のプロファイルは削除できません。別のプロファイルに切り替えてから削除してください".to_string());
+    }
+    let profile = profiles.find(profile_id)
+        .cloned()
+        .ok_or_else(|| format!("プロファイルが見つかりません: {}", profile_id))?;
+
+    profiles.profiles.retain(|p| p.id != profile_id);
+    save(app_handle, &profiles)?;
+
+    let config_dir = config_dir_of(app_handle, &profile)?;
+    if config_dir.exists() {
+        fs::remove_dir_all(&config_dir)
+            .map_err(|e| format!("Failed to remove profile config: {}", e))?;
+    }
+    if delete_data && profile.own_database {
+        let data_dir = data_dir_of(app_handle, &profile)?;
+        if data_dir.exists() {
+            fs::remove_dir_all(&data_dir)
+                .map_err(|e| format!("Failed to remove profile data: {}", e))?;
+        }
+    }
+
+    Ok(())
+}
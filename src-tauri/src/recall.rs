@@ -0,0 +1,59 @@
+use crate::database::{Citation, SemanticSearchResult};
+
+// 質問に添える過去のメッセージの最大数
+pub const RECALL_LIMIT: usize = 5;
+// これより類似度の低いメッセージは関係がないものとして使わない
+pub const MIN_SCORE: f32 = 0.3;
+// 1件の抜粋の最大文字数
+const EXCERPT_CHARS: usize = 500;
+
+// メッセージの先頭を抜粋する
+pub fn excerpt(content: &str) -> String {
+    match content.char_indices().nth(EXCERPT_CHARS) {
+        Some((end, _)) => format!("{}…", content[..end].trim_end()),
+        None => content.to_string(),
+    }
+}
+
+// 検索結果のうち類似度が十分に高いものを、番号を付けた引用にする
+pub fn citations_from(results: Vec<SemanticSearchResult>) -> Vec<Citation> {
+    results.into_iter()
+        .filter(|result| result.score >= MIN_SCORE)
+        .take(RECALL_LIMIT)
+        .enumerate()
+        .map(|(index, result)| Citation {
+            position: index + 1,
+            message_id: result.message.id,
+            session_id: result.message.session_id,
+            session_title: result.session_title,
+            role: result.message.role,
+            excerpt: excerpt(&result.message.content),
+            timestamp: result.message.timestamp,
+            score: result.score,
+        })
+        .collect()
+}
+
+// 抜粋を番号付きの参考情報として質問の前に付ける
+pub fn build_prompt(content: &str, citations: &[Citation]) -> String {
+    if citations.is_empty() {
+        return content.to_string();
+    }
+
+    let mut prompt = String::from(
+        "以下はユーザーの過去のチャットからの抜粋です。質問に関係がある場合のみ参考にし、利用した抜粋は[1]のように番号で示してください。\n\n"
+    );
+    for citation in citations {
+        let speaker = if citation.role == "user" { "ユーザー" } else { "アシスタント" };
+        // 日付（YYYY-MM-DD）だけを示す
+        let date = citation.timestamp.get(..10).unwrap_or(&citation.timestamp);
+        prompt.push_str(&format!(
+            "[{}] 「{}」より（{}、{}）\n{}\n\n",
+            citation.position, citation.session_title, speaker, date, citation.excerpt
+        ));
+    }
+    prompt.push_str("---\n\n");
+    prompt.push_str(content);
+
+    prompt
+}
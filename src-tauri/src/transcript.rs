@@ -0,0 +1,292 @@
+use crate::database::{ChatSession, Message, MessageStatus};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+// エクスポート形式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Json,
+    // JSON Lines（1行1レコード、大量の履歴向け）
+    Jsonl,
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    // ファイルダイアログ用のフィルタ名と拡張子
+    pub fn filter(&self) -> (&'static str, &'static str) {
+        match self {
+            ExportFormat::Json => ("JSON", "json"),
+            ExportFormat::Jsonl => ("JSON Lines", "jsonl"),
+            ExportFormat::Markdown => ("Markdown", "md"),
+            ExportFormat::Html => ("HTML", "html"),
+        }
+    }
+}
+
+// セッションとそのメッセージをまとめたもの
+pub struct SessionTranscript {
+    pub session: ChatSession,
+    pub messages: Vec<Message>,
+    // メッセージIDごとの自分用のメモ
+    pub notes: HashMap<String, String>,
+    // メッセージIDごとの応答したモデル（記録されていないメッセージは含まない）
+    pub models: HashMap<String, String>,
+}
+
+impl SessionTranscript {
+    // 書き出すメッセージ（生成中や失敗したプレースホルダーは除く）
+    fn completed_messages(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter().filter(|m| m.status == MessageStatus::Complete)
+    }
+
+    // 書き出すメッセージに記録されたモデル（最初に使われた順）
+    fn used_models(&self) -> Vec<&str> {
+        let mut models: Vec<&str> = Vec::new();
+        for message in self.completed_messages() {
+            if let Some(model) = self.models.get(&message.id) {
+                if !models.contains(&model.as_str()) {
+                    models.push(model);
+                }
+            }
+        }
+        models
+    }
+
+    // 直前のメッセージからモデルが変わったメッセージだけ、そのモデルを返す
+    fn model_changes(&self) -> Vec<(&Message, Option<&str>)> {
+        let mut current: Option<&str> = None;
+        self.completed_messages()
+            .map(|message| {
+                let model = self.models.get(&message.id).map(String::as_str);
+                let changed = model.filter(|m| current != Some(*m));
+                if model.is_some() {
+                    current = model;
+                }
+                (message, changed)
+            })
+            .collect()
+    }
+}
+
+// トランスクリプトを指定された形式でファイルに書き出す
+pub fn write_transcripts(
+    file_path: &str,
+    format: ExportFormat,
+    transcripts: &[SessionTranscript],
+) -> Result<(), String> {
+    let content = match format {
+        ExportFormat::Markdown => render_markdown(transcripts),
+        ExportFormat::Html => render_html(transcripts),
+        ExportFormat::Json | ExportFormat::Jsonl => {
+            return Err("JSON形式はトランスクリプトとして出力できません".to_string())
+        }
+    };
+
+    fs::write(file_path, content)
+        .map_err(|e| format!("Failed to write file: {}", e))
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "User",
+        "assistant" => "Assistant",
+        other => other,
+    }
+}
+
+// YAMLのダブルクォート文字列としてそのまま使えるようにJSON文字列でエスケープする
+fn yaml_str(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+pub fn render_markdown(transcripts: &[SessionTranscript]) -> String {
+    let mut out = String::new();
+
+    // フロントマター
+    out.push_str("---\n");
+    out.push_str(&format!("exported_at: {}\n", yaml_str(&Utc::now().to_rfc3339())));
+    out.push_str("sessions:\n");
+    for t in transcripts {
+        out.push_str(&format!("  - id: {}\n", yaml_str(&t.session.id)));
+        out.push_str(&format!("    title: {}\n", yaml_str(&t.session.title)));
+        out.push_str(&format!("    created_at: {}\n", yaml_str(&t.session.created_at)));
+        out.push_str(&format!("    updated_at: {}\n", yaml_str(&t.session.updated_at)));
+        out.push_str(&format!("    message_count: {}\n", t.completed_messages().count()));
+        let models: Vec<String> = t.used_models().into_iter().map(yaml_str).collect();
+        out.push_str(&format!("    models: [{}]\n", models.join(", ")));
+    }
+    out.push_str("---\n");
+
+    for (i, t) in transcripts.iter().enumerate() {
+        if i > 0 {
+            out.push_str("\n---\n");
+        }
+        out.push_str(&format!("\n# {}\n", t.session.title));
+
+        for (message, model) in t.model_changes() {
+            out.push_str(&format!(
+                "\n## {} ({})\n\n",
+                role_label(&message.role),
+                message.timestamp
+            ));
+            if let Some(model) = model {
+                out.push_str(&format!("*Model: {}*\n\n", model));
+            }
+            out.push_str(&format!("{}\n", message.content.trim_end()));
+            if let Some(note) = t.notes.get(&message.id) {
+                out.push_str("\n> **メモ:**\n");
+                for line in note.lines() {
+                    out.push_str(&format!(">{}{}\n", if line.is_empty() { "" } else { " " }, line));
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// インラインコード（`code`）だけを変換し、残りはエスケープする
+fn render_inline(text: &str) -> String {
+    let mut out = String::new();
+    for (i, part) in text.split('`').enumerate() {
+        if i % 2 == 1 {
+            out.push_str(&format!("<code>{}</code>", escape_html(part)));
+        } else {
+            out.push_str(&escape_html(part).replace('\n', "<br>\n"));
+        }
+    }
+    out
+}
+
+fn render_paragraphs(text: &str, out: &mut String) {
+    for paragraph in text.split("\n\n") {
+        let paragraph = paragraph.trim();
+        if !paragraph.is_empty() {
+            out.push_str(&format!("<p>{}</p>\n", render_inline(paragraph)));
+        }
+    }
+}
+
+// メッセージ本文をHTMLに変換する（フェンス付きコードブロックに対応）
+fn render_content(content: &str) -> String {
+    let mut out = String::new();
+    let mut text = String::new();
+    let mut code = String::new();
+    let mut code_lang: Option<String> = None;
+
+    for line in content.lines() {
+        let trimmed = line.trim_start();
+        match &code_lang {
+            None if trimmed.starts_with("```") => {
+                render_paragraphs(&text, &mut out);
+                text.clear();
+                code_lang = Some(trimmed.trim_start_matches('`').trim().to_string());
+            }
+            None => {
+                text.push_str(line);
+                text.push('\n');
+            }
+            Some(lang) if trimmed.starts_with("```") => {
+                let class = if lang.is_empty() {
+                    String::new()
+                } else {
+                    format!(" class=\"language-{}\"", escape_html(lang))
+                };
+                out.push_str(&format!("<pre><code{}>{}</code></pre>\n", class, escape_html(&code)));
+                code.clear();
+                code_lang = None;
+            }
+            Some(_) => {
+                code.push_str(line);
+                code.push('\n');
+            }
+        }
+    }
+
+    // 閉じられていないコードブロックもコードとして出力する
+    if code_lang.is_some() {
+        out.push_str(&format!("<pre><code>{}</code></pre>\n", escape_html(&code)));
+    }
+    render_paragraphs(&text, &mut out);
+
+    out
+}
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif; max-width: 860px; margin: 2rem auto; padding: 0 1rem; color: #212121; }
+header.meta { color: #757575; font-size: 0.9rem; margin-bottom: 2rem; }
+section.session { margin-bottom: 3rem; }
+.message { border-radius: 0.5rem; padding: 0.75rem 1rem; margin: 1rem 0; }
+.message.user { background: #e3f2fd; }
+.message.assistant { background: #f5f5f5; }
+.message .role { font-weight: bold; }
+.message .timestamp { color: #757575; font-size: 0.8rem; margin-left: 0.5rem; }
+.message .model { color: #757575; font-size: 0.8rem; margin-left: 0.5rem; }
+pre { background: #263238; color: #eceff1; padding: 0.75rem; border-radius: 0.25rem; overflow-x: auto; }
+code { font-family: 'SFMono-Regular', Consolas, monospace; }
+p code { background: #eceff1; padding: 0.1rem 0.3rem; border-radius: 0.2rem; }
+";
+
+pub fn render_html(transcripts: &[SessionTranscript]) -> String {
+    let title = match transcripts {
+        [single] => single.session.title.clone(),
+        _ => "チャット履歴".to_string(),
+    };
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"ja\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+    out.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", HTML_STYLE));
+    out.push_str(&format!(
+        "<header class=\"meta\">Exported at: {}</header>\n",
+        escape_html(&Utc::now().to_rfc3339())
+    ));
+
+    for t in transcripts {
+        out.push_str(&format!("<section class=\"session\" id=\"{}\">\n", escape_html(&t.session.id)));
+        out.push_str(&format!("<h1>{}</h1>\n", escape_html(&t.session.title)));
+        out.push_str(&format!(
+            "<p class=\"meta\">Created: {} / Updated: {}</p>\n",
+            escape_html(&t.session.created_at),
+            escape_html(&t.session.updated_at)
+        ));
+
+        for (message, model) in t.model_changes() {
+            let model = model
+                .map(|m| format!("<span class=\"model\">{}</span>", escape_html(m)))
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "<article class=\"message {}\">\n<div><span class=\"role\">{}</span><span class=\"timestamp\">{}</span>{}</div>\n{}</article>\n",
+                escape_html(&message.role),
+                escape_html(role_label(&message.role)),
+                escape_html(&message.timestamp),
+                model,
+                render_content(&message.content)
+            ));
+        }
+
+        out.push_str("</section>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
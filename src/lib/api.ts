@@ -1,13 +1,37 @@
 import { invoke } from "@tauri-apps/api/core";
-import type { ChatSession, Message, Config } from './types';
+import type { ChatSession, DatabaseStatus, DatabaseStats, MaintenanceReport, RepairReport, EmbeddingStatus, SemanticSearchResult, BackupInfo, Message, MessagePage, ChatTurn, Config, Trash, Folder, Tag, SessionQuery, ExportFormat, ImportSource, ImportReport, MergeStrategy, SessionMergeMode, Citation, MessageAnnotation, Bookmark, KnowledgeCollection, IngestReport, Profile, ProfileList, StartupDiagnostics } from './types';
 
 // MCP関連
 export async function initializeMcp(apiKey: string): Promise<string> {
   return invoke('initialize_mcp', { apiKey });
 }
 
-export async function sendMessage(content: string, sessionId: string): Promise<string> {
-  return invoke('send_message', { content, sessionId });
+// ユーザーメッセージと応答はバックエンドで保存される
+// recallを指定すると、他のセッションから関連するメッセージを探して質問に添える
+export async function sendMessage(content: string, sessionId: string, recall = false): Promise<ChatTurn> {
+  return invoke('send_message', { content, sessionId, recall });
+}
+
+// 応答ごとの参考にした過去のメッセージ（キーは応答のID）
+export async function getSessionCitations(sessionId: string): Promise<Record<string, Citation[]>> {
+  return invoke('get_session_citations', { sessionId });
+}
+
+// メッセージのスターとメモ
+export async function setMessageBookmarked(messageId: string, bookmarked: boolean): Promise<void> {
+  return invoke('set_message_bookmarked', { messageId, bookmarked });
+}
+
+export async function setMessageNote(messageId: string, note: string | null): Promise<void> {
+  return invoke('set_message_note', { messageId, note });
+}
+
+export async function getSessionAnnotations(sessionId: string): Promise<Record<string, MessageAnnotation>> {
+  return invoke('get_session_annotations', { sessionId });
+}
+
+export async function getBookmarks(): Promise<Bookmark[]> {
+  return invoke('get_bookmarks');
 }
 
 // データベース関連
@@ -15,12 +39,88 @@ export async function createChatSession(title: string): Promise<string> {
   return invoke('create_chat_session', { title });
 }
 
-export async function getChatSessions(): Promise<ChatSession[]> {
-  return invoke('get_chat_sessions');
+export async function renameChatSession(sessionId: string, title: string): Promise<void> {
+  return invoke('rename_chat_session', { sessionId, title });
+}
+
+export async function getChatSessions(query?: SessionQuery): Promise<ChatSession[]> {
+  return invoke('get_chat_sessions', { query });
+}
+
+export async function countChatSessions(query?: SessionQuery): Promise<number> {
+  return invoke('count_chat_sessions', { query });
+}
+
+// 指定したメッセージまでをコピーした新しいセッションを作成する
+export async function forkSession(sessionId: string, messageId: string): Promise<string> {
+  return invoke('fork_session', { sessionId, messageId });
+}
+
+// 指定したメッセージ以降を新しいセッションに移す
+export async function splitSession(sessionId: string, messageId: string): Promise<string> {
+  return invoke('split_session', { sessionId, messageId });
+}
+
+// 統合元のセッションのメッセージを統合先に移し、統合元を削除する
+export async function mergeSessions(
+  targetSessionId: string,
+  sourceSessionIds: string[],
+  mode: SessionMergeMode = 'append'
+): Promise<void> {
+  return invoke('merge_sessions', { targetSessionId, sourceSessionIds, mode });
+}
+
+export async function setSessionPinned(sessionId: string, pinned: boolean): Promise<void> {
+  return invoke('set_session_pinned', { sessionId, pinned });
+}
+
+export async function setSessionArchived(sessionId: string, archived: boolean): Promise<void> {
+  return invoke('set_session_archived', { sessionId, archived });
+}
+
+export async function moveSessionToFolder(sessionId: string, folderId: string | null): Promise<void> {
+  return invoke('move_session_to_folder', { sessionId, folderId });
+}
+
+export async function setSessionTags(sessionId: string, tags: string[]): Promise<void> {
+  return invoke('set_session_tags', { sessionId, tags });
 }
 
-export async function getChatMessages(sessionId: string): Promise<Message[]> {
-  return invoke('get_chat_messages', { sessionId });
+// フォルダ・タグ関連
+export async function getFolders(): Promise<Folder[]> {
+  return invoke('get_folders');
+}
+
+export async function createFolder(name: string, parentId: string | null = null): Promise<string> {
+  return invoke('create_folder', { name, parentId });
+}
+
+export async function renameFolder(folderId: string, name: string): Promise<void> {
+  return invoke('rename_folder', { folderId, name });
+}
+
+export async function moveFolder(folderId: string, parentId: string | null): Promise<void> {
+  return invoke('move_folder', { folderId, parentId });
+}
+
+export async function deleteFolder(folderId: string): Promise<void> {
+  return invoke('delete_folder', { folderId });
+}
+
+export async function getTags(): Promise<Tag[]> {
+  return invoke('get_tags');
+}
+
+export async function deleteTag(tagId: string): Promise<void> {
+  return invoke('delete_tag', { tagId });
+}
+
+export async function getChatMessages(sessionId: string, page?: MessagePage): Promise<Message[]> {
+  return invoke('get_chat_messages', { sessionId, page });
+}
+
+export async function countChatMessages(sessionId: string): Promise<number> {
+  return invoke('count_chat_messages', { sessionId });
 }
 
 export async function addChatMessage(
@@ -35,6 +135,35 @@ export async function deleteChatSession(sessionId: string): Promise<void> {
   return invoke('delete_chat_session', { sessionId });
 }
 
+export async function deleteChatMessage(messageId: string): Promise<void> {
+  return invoke('delete_chat_message', { messageId });
+}
+
+// ゴミ箱関連
+export async function getTrash(): Promise<Trash> {
+  return invoke('get_trash');
+}
+
+export async function restoreChatSession(sessionId: string): Promise<void> {
+  return invoke('restore_chat_session', { sessionId });
+}
+
+export async function restoreChatMessage(messageId: string): Promise<void> {
+  return invoke('restore_chat_message', { messageId });
+}
+
+export async function purgeChatSession(sessionId: string): Promise<void> {
+  return invoke('purge_chat_session', { sessionId });
+}
+
+export async function purgeChatMessage(messageId: string): Promise<void> {
+  return invoke('purge_chat_message', { messageId });
+}
+
+export async function emptyTrash(): Promise<void> {
+  return invoke('empty_trash');
+}
+
 // 設定関連
 export async function getConfig(): Promise<Config> {
   return invoke('get_config');
@@ -44,11 +173,137 @@ export async function saveConfig(config: Config): Promise<void> {
   return invoke('save_config_command', { config });
 }
 
+// プロファイル関連
+export async function getProfiles(): Promise<ProfileList> {
+  return invoke('get_profiles');
+}
+
+export async function createProfile(name: string, ownDatabase: boolean): Promise<Profile> {
+  return invoke('create_profile', { name, ownDatabase });
+}
+
+// 切り替えると設定とデータベースが読み込み直されるので、画面も読み込み直す
+export async function switchProfile(profileId: string): Promise<void> {
+  return invoke('switch_profile', { profileId });
+}
+
+export async function deleteProfile(profileId: string, deleteData = false): Promise<void> {
+  return invoke('delete_profile', { profileId, deleteData });
+}
+
 // エクスポート/インポート関連
-export async function exportChatHistory(): Promise<string> {
-  return invoke('export_chat_history');
+export async function exportChatHistory(
+  format: ExportFormat = 'json',
+  sessionIds?: string[]
+): Promise<string> {
+  return invoke('export_chat_history', { format, sessionIds });
+}
+
+export async function importChatHistory(options: {
+  filePath?: string;
+  source?: ImportSource;
+  strategy?: MergeStrategy;
+  dryRun?: boolean;
+} = {}): Promise<ImportReport> {
+  return invoke('import_chat_history', options);
+}
+
+// データベースの暗号化関連
+export async function getDatabaseStatus(): Promise<DatabaseStatus> {
+  return invoke('get_database_status');
+}
+
+// 起動時（またはプロファイルの切り替え時）に見つかった問題
+export async function getStartupDiagnostics(): Promise<StartupDiagnostics> {
+  return invoke('get_startup_diagnostics');
+}
+
+export async function unlockDatabase(passphrase: string): Promise<void> {
+  return invoke('unlock_database', { passphrase });
+}
+
+export async function lockDatabase(): Promise<void> {
+  return invoke('lock_database');
+}
+
+export async function enableDatabaseEncryption(passphrase: string): Promise<void> {
+  return invoke('enable_database_encryption', { passphrase });
+}
+
+export async function disableDatabaseEncryption(currentPassphrase: string): Promise<void> {
+  return invoke('disable_database_encryption', { currentPassphrase });
+}
+
+export async function changeDatabasePassphrase(currentPassphrase: string, newPassphrase: string): Promise<void> {
+  return invoke('change_database_passphrase', { currentPassphrase, newPassphrase });
+}
+
+// 自動ロックのために操作があったことを通知する
+export async function recordActivity(): Promise<void> {
+  return invoke('record_activity');
+}
+
+// バックアップ関連
+export async function createBackup(): Promise<BackupInfo> {
+  return invoke('create_backup');
+}
+
+export async function listBackups(): Promise<BackupInfo[]> {
+  return invoke('list_backups');
+}
+
+export async function restoreBackup(name: string, options: { passphrase?: string; restoreConfig?: boolean } = {}): Promise<void> {
+  return invoke('restore_backup', {
+    name,
+    passphrase: options.passphrase,
+    restoreConfig: options.restoreConfig ?? false
+  });
+}
+
+// データベースの統計とメンテナンス
+export async function getDatabaseStats(): Promise<DatabaseStats> {
+  return invoke('database_stats');
+}
+
+export async function runDatabaseMaintenance(): Promise<MaintenanceReport> {
+  return invoke('run_database_maintenance');
+}
+
+export async function repairDatabase(): Promise<RepairReport> {
+  return invoke('repair_database');
+}
+
+// セマンティック検索
+export async function semanticSearch(query: string, limit?: number): Promise<SemanticSearchResult[]> {
+  return invoke('semantic_search', { query, limit });
+}
+
+export async function getEmbeddingStatus(): Promise<EmbeddingStatus> {
+  return invoke('get_embedding_status');
+}
+
+// 資料（ナレッジベース）
+export async function getKnowledgeCollections(): Promise<KnowledgeCollection[]> {
+  return invoke('get_knowledge_collections');
+}
+
+export async function createKnowledgeCollection(name: string, folder: string): Promise<string> {
+  return invoke('create_knowledge_collection', { name, folder });
 }
 
-export async function importChatHistory(): Promise<string> {
-  return invoke('import_chat_history');
-}
\ No newline at end of file
+export async function deleteKnowledgeCollection(collectionId: string): Promise<void> {
+  return invoke('delete_knowledge_collection', { collectionId });
+}
+
+// フォルダから資料を取り込む（変更されたファイルだけを取り込み直す）
+export async function ingestKnowledgeCollection(collectionId: string): Promise<IngestReport> {
+  return invoke('ingest_knowledge_collection', { collectionId });
+}
+
+export async function getSessionCollections(sessionId: string): Promise<string[]> {
+  return invoke('get_session_collections', { sessionId });
+}
+
+export async function setSessionCollections(sessionId: string, collectionIds: string[]): Promise<void> {
+  return invoke('set_session_collections', { sessionId, collectionIds });
+}
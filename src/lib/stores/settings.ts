@@ -2,11 +2,23 @@ import { writable } from 'svelte/store';
 import type { Config } from '../types';
 
 const initialState: Config = {
+  version: 4,
   apiKey: '',
   model: 'claude-3-opus-20240229',
   theme: 'light',
-  maxHistory: 100,
+  maxHistory: 0,
   autoCreateChat: true, // デフォルトでは自動作成を有効にする
+  retentionMaxAgeDays: null,
+  trashRetentionDays: 0,
+  autoTitle: true,
+  autoLockMinutes: null,
+  backupDir: null,
+  backupIntervalHours: 24,
+  backupKeep: 7,
+  semanticSearch: false,
+  embeddingBaseUrl: 'https://api.openai.com',
+  embeddingApiKey: null,
+  embeddingModel: 'text-embedding-3-small',
 };
 
 export const settingsStore = writable<Config>(initialState);
\ No newline at end of file
@@ -3,22 +3,167 @@ export interface ChatSession {
   title: string;
   createdAt: string;
   updatedAt: string;
+  pinned: boolean;
+  archived: boolean;
+  folderId: string | null;
+  tags: string[];
+  forkedFromSessionId: string | null; // 分岐元のセッション（fork_sessionで作成した場合）
+  forkedFromMessageId: string | null;
 }
 
+export interface Folder {
+  id: string;
+  name: string;
+  parentId: string | null;
+  createdAt: string;
+}
+
+export interface Tag {
+  id: string;
+  name: string;
+  sessionCount: number;
+}
+
+export interface SessionQuery {
+  folderId?: string; // ''の場合はフォルダに入っていないセッション
+  includeSubfolders?: boolean;
+  tagId?: string;
+  archived?: boolean; // 未指定の場合はアーカイブ済みも含める
+  limit?: number;
+  offset?: number;
+}
+
+// メッセージのページング条件（before/afterはメッセージID）
+export interface MessagePage {
+  before?: string;
+  after?: string;
+  limit?: number; // before/afterを指定しない場合は最新のlimit件
+}
+
+// pending: 応答を生成中、error: 応答の生成に失敗（contentにエラー内容）
+export type MessageStatus = 'complete' | 'pending' | 'error';
+
 export interface Message {
   id: string;
   sessionId: string;
   role: 'user' | 'assistant';
   content: string;
   timestamp: string;
+  status: MessageStatus;
+}
+
+// send_messageで保存された1往復分のメッセージ
+export interface ChatTurn {
+  userMessage: Message;
+  assistantMessage: Message;
+  citations: Citation[]; // 思い出しモードで応答に添えた過去のメッセージ
+}
+
+// 応答の参考にした過去のメッセージ
+export interface Citation {
+  position: number; // プロンプト内の番号（[1]など）
+  messageId: string;
+  sessionId: string;
+  sessionTitle: string;
+  role: 'user' | 'assistant';
+  excerpt: string;
+  timestamp: string;
+  score: number;
+}
+
+// メッセージのスターとメモ
+export interface MessageAnnotation {
+  starred: boolean;
+  note: string | null;
+}
+
+// スターを付けたメッセージ
+export interface Bookmark {
+  message: Message;
+  sessionTitle: string;
+  note: string | null;
+  bookmarkedAt: string;
+}
+
+// 設定（APIキーやモデルなど）とデータベースを切り替えるためのプロファイル
+export interface Profile {
+  id: string;
+  name: string;
+  ownDatabase: boolean; // falseの場合は既定のデータベースを共有する
+}
+
+export interface ProfileList {
+  active: string; // 使用中のプロファイルのID
+  profiles: Profile[];
 }
 
 export interface Config {
+  version: number; // 設定ファイルの形式のバージョン
   apiKey: string;
   model: string;
   theme: 'light' | 'dark';
-  maxHistory: number;
+  maxHistory: number; // 保存するセッションの上限（0で無制限）
   autoCreateChat: boolean; // 新規チャットの自動作成を制御
+  retentionMaxAgeDays: number | null; // この日数より古いセッションをゴミ箱へ移動（nullで無効）
+  trashRetentionDays: number; // ゴミ箱の項目を完全に削除するまでの日数（0で無効）
+  autoTitle: boolean; // 最初のやり取りの後にタイトルを自動生成する
+  autoLockMinutes: number | null; // 暗号化されたデータベースを自動でロックするまでの分数（nullで無効）
+  backupDir: string | null; // バックアップの保存先（nullでアプリのデータディレクトリ）
+  backupIntervalHours: number; // 自動バックアップの間隔（0で無効）
+  backupKeep: number; // 残しておくバックアップの数（0で無制限）
+  semanticSearch: boolean; // メッセージの埋め込みを計算して意味の近いメッセージを検索できるようにする
+  embeddingBaseUrl: string; // OpenAI互換の埋め込みAPIのURL
+  embeddingApiKey: string | null;
+  embeddingModel: string;
+}
+
+export interface BackupInfo {
+  name: string;
+  createdAt: string;
+  size: number; // バイト
+  encrypted: boolean;
+  hasConfig: boolean;
+}
+
+export interface DatabaseStatus {
+  encrypted: boolean;
+  locked: boolean; // 暗号化されたデータベースがまだ開かれていない
+  inMemory: boolean; // ファイルを開けなかったため、一時的なデータベースで動作している
+}
+
+// 起動時に見つかった設定やデータベースの問題
+export interface StartupDiagnostics {
+  configError: string | null; // 設定を読み込めず、デフォルト設定で動作している
+  configPreservedPath: string | null; // 読み込めなかった設定ファイルの移動先
+  databaseError: string | null;
+  inMemory: boolean; // 終了すると消える一時的なデータベースで動作している
+}
+
+// タイトルが自動生成されたときのイベント
+export interface SessionTitleUpdated {
+  sessionId: string;
+  title: string;
+}
+
+// セッションを分岐したときのイベント
+export interface SessionForked {
+  sessionId: string;
+  sourceSessionId: string;
+}
+
+export interface TrashedSession extends ChatSession {
+  deletedAt: string;
+  messageCount: number;
+}
+
+export interface TrashedMessage extends Omit<Message, 'status'> {
+  sessionTitle: string;
+  deletedAt: string;
+}
+
+export interface Trash {
+  sessions: TrashedSession[];
+  messages: TrashedMessage[];
 }
 
 export interface ExportData {
@@ -26,4 +171,132 @@ export interface ExportData {
   messages: Message[];
   version: string;
   exportedAt: string;
-}
\ No newline at end of file
+}
+
+export type ExportFormat = 'json' | 'jsonl' | 'markdown' | 'html';
+
+export type ImportSource = 'native' | 'chatgpt' | 'claude' | 'jsonl';
+
+export type MergeStrategy = 'skip' | 'overwrite' | 'copy';
+
+// セッションの統合方法（append: 末尾に追加、interleave: 時系列で並べ直す）
+export type SessionMergeMode = 'append' | 'interleave';
+
+export interface ImportSummary {
+  sessionsInserted: number;
+  sessionsUpdated: number;
+  sessionsSkipped: number;
+  messagesInserted: number;
+  messagesUpdated: number;
+  messagesSkipped: number;
+  orphanMessages: number;
+}
+
+export interface ImportedSessionSummary {
+  id: string;
+  title: string;
+  messageCount: number;
+}
+
+export interface ImportReport {
+  source: ImportSource;
+  filePath: string;
+  dryRun: boolean;
+  strategy: MergeStrategy;
+  sessions: ImportedSessionSummary[];
+  messageCount: number;
+  skippedMessages: number;
+  summary: ImportSummary;
+}
+
+// エクスポートではメッセージ数、インポートではファイルのバイト数
+export interface TransferProgress {
+  processed: number;
+  total: number;
+}
+
+export interface SessionSize {
+  id: string;
+  title: string;
+  messageCount: number;
+  characters: number;
+}
+
+export interface ModelUsage {
+  model: string | null; // モデルを記録する前の応答はnull
+  messageCount: number;
+  characters: number;
+  estimatedTokens: number;
+}
+
+export interface DatabaseStats {
+  sessionCount: number;
+  archivedSessionCount: number;
+  messageCount: number;
+  trashedSessionCount: number;
+  trashedMessageCount: number;
+  totalCharacters: number;
+  estimatedTokens: number;
+  fileSize: number; // バイト
+  freeSize: number; // VACUUMで回収できる領域（バイト）
+  largestSessions: SessionSize[];
+  models: ModelUsage[];
+}
+
+export interface EmbeddingStatus {
+  model: string;
+  indexed: number; // 埋め込みを計算済みのメッセージ数
+  failed: number; // 埋め込みAPIに拒否されたため飛ばしたメッセージ数
+  total: number; // 検索対象になるメッセージ数
+}
+
+export interface SemanticSearchResult {
+  message: Message;
+  sessionTitle: string;
+  score: number; // コサイン類似度
+}
+
+// 資料のコレクション
+export interface KnowledgeCollection {
+  id: string;
+  name: string;
+  folder: string; // 資料を取り込むフォルダ
+  createdAt: string;
+  updatedAt: string;
+  documentCount: number;
+  chunkCount: number;
+}
+
+// 資料の取り込みの結果
+export interface IngestReport {
+  added: number;
+  updated: number; // 内容が変わったため取り込み直した資料
+  unchanged: number;
+  removed: number; // フォルダから無くなったため削除した資料
+  chunks: number;
+  failed: { path: string; error: string }[];
+}
+
+// 資料の取り込みの進捗
+export interface IngestProgress {
+  collectionId: string;
+  processed: number;
+  total: number;
+}
+
+// 修復した不整合の件数
+export interface RepairReport {
+  orphanMessages: number; // 存在しないセッションを参照していたメッセージ
+  recoveredSessions: number; // 孤立したメッセージのために作り直したセッション
+  orphanSessionTags: number;
+  orphanSessionFolders: number;
+  orphanFolderParents: number;
+}
+
+export interface MaintenanceReport {
+  integrity: string[]; // 問題がなければ['ok']
+  sizeBefore: number;
+  sizeAfter: number;
+  reclaimedBytes: number;
+  optimizedFtsTables: string[];
+}